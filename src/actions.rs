@@ -0,0 +1,264 @@
+use crate::notifier::{Id, Notifier, Response};
+use notify_rust::{Hint, Notification};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/* the prompt is closed if the user does not react */
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(60);
+/* the prompt is closed if the device stops advertising */
+const DEVICE_GONE: Duration = Duration::from_secs(20);
+/* a dismissed device is not prompted again for this time */
+const DISMISS_COOLDOWN: Duration = Duration::from_secs(5 * 60);
+
+/* reaction of the user on a prompt, identified by the device address */
+#[derive(Debug, Clone, PartialEq)]
+pub enum UserAction {
+    Connect { address: String },
+    Dismiss { address: String },
+}
+
+impl UserAction {
+    fn from_response(address: String, response: &Response) -> UserAction {
+        match response {
+            Response::Action(action) if action == "connect" => UserAction::Connect { address },
+            _ => UserAction::Dismiss { address },
+        }
+    }
+}
+
+struct Prompt {
+    id: Id,
+    shown: Instant,
+    last_seen: Instant,
+}
+
+/* Notifications with actions, one per device in pairing mode. The responses
+ * of the notifier are matched against the shown prompts. */
+#[derive(Default)]
+pub struct Prompts {
+    prompts: HashMap<String, Prompt>,
+    dismissed: HashMap<String, Instant>,
+}
+
+impl Prompts {
+    /* shows the notification, unless the device is already prompted */
+    pub fn prompt(&mut self, notifier: &mut dyn Notifier, address: String, notification: Notification) {
+        self.prompt_at(notifier, address, notification, Instant::now());
+    }
+
+    fn prompt_at(&mut self, notifier: &mut dyn Notifier, address: String, notification: Notification, now: Instant) {
+        if let Some(prompt) = self.prompts.get_mut(&address) {
+            prompt.last_seen = now;
+            return;
+        }
+        if self.dismissed.get(&address).is_some_and(|at| now.duration_since(*at) < DISMISS_COOLDOWN) {
+            return;
+        }
+        match notifier.show(&notification) {
+            Some(id) => {
+                self.prompts.insert(address, Prompt {
+                    id,
+                    shown: now,
+                    last_seen: now,
+                });
+            }
+            // a prompt without a notification cannot be answered
+            None => {
+                self.dismissed.insert(address, now);
+            }
+        }
+    }
+
+    /* the action of the user, if the response belongs to a prompt */
+    pub fn respond(&mut self, id: Id, response: &Response) -> Option<UserAction> {
+        self.respond_at(id, response, Instant::now())
+    }
+
+    fn respond_at(&mut self, id: Id, response: &Response, now: Instant) -> Option<UserAction> {
+        let address = self.prompts.iter()
+            .find(|(_, prompt)| prompt.id == id)
+            .map(|(address, _)| address.clone())?;
+        self.prompts.remove(&address);
+        let action = UserAction::from_response(address, response);
+        if let UserAction::Dismiss { address } = &action {
+            self.dismissed.insert(address.clone(), now);
+        }
+        Some(action)
+    }
+
+    /* closes the prompts of devices which went away or were not answered */
+    pub fn expire(&mut self, notifier: &mut dyn Notifier) {
+        self.expire_at(notifier, Instant::now());
+    }
+
+    fn expire_at(&mut self, notifier: &mut dyn Notifier, now: Instant) {
+        self.prompts.retain(|address, prompt| {
+            let timed_out = now.duration_since(prompt.shown) >= PROMPT_TIMEOUT;
+            if now.duration_since(prompt.last_seen) < DEVICE_GONE && !timed_out {
+                return true;
+            }
+            notifier.close(prompt.id);
+            if timed_out {
+                self.dismissed.insert(address.clone(), now);
+            }
+            false
+        });
+        self.dismissed.retain(|_, at| now.duration_since(*at) < DISMISS_COOLDOWN);
+    }
+}
+
+/* button of a nearby card */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CardAction {
+    Connect,
+    Disconnect,
+    Mute,
+    Hide,
+    Details,
+}
+
+impl CardAction {
+    const ALL: [CardAction; 5] = [
+        CardAction::Connect,
+        CardAction::Disconnect,
+        CardAction::Mute,
+        CardAction::Hide,
+        CardAction::Details,
+    ];
+
+    fn key(&self) -> &'static str {
+        match self {
+            CardAction::Connect => "card-connect",
+            CardAction::Disconnect => "card-disconnect",
+            CardAction::Mute => "card-mute",
+            CardAction::Hide => "card-hide",
+            CardAction::Details => "card-details",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            CardAction::Connect => "Connect",
+            CardAction::Disconnect => "Disconnect",
+            CardAction::Mute => "Mute for today",
+            CardAction::Hide => "Never show again",
+            CardAction::Details => "Details",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<CardAction> {
+        CardAction::ALL.into_iter().find(|action| action.key() == key)
+    }
+}
+
+/* Replaces the buttons of a card. Connect or disconnect is offered only
+ * for a device which is known to BlueZ, mute and hide only for a device
+ * with a key for the registry. */
+pub fn card_actions(notification: &mut Notification, connected: Option<bool>, has_key: bool) {
+    notification.actions.clear();
+    let actions = CardAction::ALL.into_iter().filter(|action| match action {
+        CardAction::Connect => connected == Some(false),
+        CardAction::Disconnect => connected == Some(true),
+        CardAction::Mute | CardAction::Hide => has_key,
+        _ => true,
+    });
+    for action in actions {
+        notification.action(action.key(), action.label());
+    }
+    // the card stays open after a button was clicked
+    notification.hint(Hint::Resident(true));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifier::{Record, Recorder};
+    use tokio::sync::mpsc;
+
+    const ADDRESS: &str = "AA:BB:CC:DD:EE:01";
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn recorder() -> Recorder {
+        Recorder::new(mpsc::channel(1).0)
+    }
+
+    /* shown and closed ids */
+    fn log(recorder: &Recorder) -> Vec<String> {
+        recorder.records().iter()
+            .map(|record| match record {
+                Record::Shown(id, _) => format!("shown {}", id),
+                Record::Updated(id, _) => format!("updated {}", id),
+                Record::Closed(id) => format!("closed {}", id),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unanswered_prompt_times_out_and_cools_down() {
+        let mut recorder = recorder();
+        let mut prompts = Prompts::default();
+        let start = Instant::now();
+        // the device keeps advertising while the prompt is open
+        for second in (0..60).step_by(10) {
+            prompts.prompt_at(&mut recorder, ADDRESS.to_string(), Notification::new(), start + second * SECOND);
+            prompts.expire_at(&mut recorder, start + second * SECOND);
+        }
+        assert_eq!(log(&recorder), ["shown 1"]);
+        prompts.expire_at(&mut recorder, start + 60 * SECOND);
+        assert_eq!(log(&recorder), ["shown 1", "closed 1"]);
+
+        prompts.prompt_at(&mut recorder, ADDRESS.to_string(), Notification::new(), start + 61 * SECOND);
+        assert_eq!(log(&recorder).len(), 2);
+        prompts.expire_at(&mut recorder, start + 360 * SECOND);
+        prompts.prompt_at(&mut recorder, ADDRESS.to_string(), Notification::new(), start + 360 * SECOND);
+        assert_eq!(log(&recorder), ["shown 1", "closed 1", "shown 2"]);
+    }
+
+    #[test]
+    fn prompt_is_closed_when_the_device_is_gone() {
+        let mut recorder = recorder();
+        let mut prompts = Prompts::default();
+        let start = Instant::now();
+        prompts.prompt_at(&mut recorder, ADDRESS.to_string(), Notification::new(), start);
+        prompts.expire_at(&mut recorder, start + 19 * SECOND);
+        assert_eq!(log(&recorder), ["shown 1"]);
+        prompts.expire_at(&mut recorder, start + 20 * SECOND);
+        assert_eq!(log(&recorder), ["shown 1", "closed 1"]);
+
+        // a device which went away is not dismissed
+        prompts.prompt_at(&mut recorder, ADDRESS.to_string(), Notification::new(), start + 21 * SECOND);
+        assert_eq!(log(&recorder), ["shown 1", "closed 1", "shown 2"]);
+    }
+
+    #[test]
+    fn dismissed_device_is_not_prompted_again_for_a_while() {
+        let mut recorder = recorder();
+        let mut prompts = Prompts::default();
+        let start = Instant::now();
+        prompts.prompt_at(&mut recorder, ADDRESS.to_string(), Notification::new(), start);
+        assert_eq!(prompts.respond_at(2, &Response::Closed, start), None);
+        assert_eq!(prompts.respond_at(1, &Response::Closed, start + SECOND),
+            Some(UserAction::Dismiss { address: ADDRESS.to_string() }));
+
+        prompts.prompt_at(&mut recorder, ADDRESS.to_string(), Notification::new(), start + 299 * SECOND);
+        assert_eq!(log(&recorder), ["shown 1"]);
+        prompts.prompt_at(&mut recorder, ADDRESS.to_string(), Notification::new(), start + 301 * SECOND);
+        assert_eq!(log(&recorder), ["shown 1", "shown 2"]);
+        assert_eq!(prompts.respond_at(2, &Response::Action("connect".to_string()), start + 302 * SECOND),
+            Some(UserAction::Connect { address: ADDRESS.to_string() }));
+    }
+
+    fn buttons(connected: Option<bool>, has_key: bool) -> Vec<String> {
+        let mut notification = Notification::new();
+        card_actions(&mut notification, connected, has_key);
+        notification.actions.iter().step_by(2).cloned().collect()
+    }
+
+    #[test]
+    fn card_buttons_follow_the_device() {
+        assert_eq!(buttons(Some(false), true), ["card-connect", "card-mute", "card-hide", "card-details"]);
+        assert_eq!(buttons(Some(true), true), ["card-disconnect", "card-mute", "card-hide", "card-details"]);
+        // someone else's device cannot be muted by its rotating address
+        assert_eq!(buttons(None, false), ["card-details"]);
+    }
+}
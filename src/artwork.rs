@@ -0,0 +1,203 @@
+use crate::config::{ArtworkConfig, DeviceConfig, APP_DIR};
+use crate::gauge;
+use crate::proximity::{Color, Model, PairedMessage};
+use image::RgbaImage;
+use notify_rust::{Hint, Image, Notification};
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::path::PathBuf;
+
+const AIRPODS: &[u8] = include_bytes!("../res/airpods.png");
+const AIRPODSPRO: &[u8] = include_bytes!("../res/airpodspro.png");
+const AIRPODSMAX_WHITE: &[u8] = include_bytes!("../res/airpodsmax-white.png");
+const BEATSFLEX_BLACK: &[u8] = include_bytes!("../res/beatsflex-black.png");
+const BEATSSOLO3_WHITE: &[u8] = include_bytes!("../res/beatssolo3-white.png");
+const BEATSSTUDIO3_BLACK: &[u8] = include_bytes!("../res/beatsstudio3-black.png");
+const BEATSX_BLACK: &[u8] = include_bytes!("../res/beatsx-black.png");
+const POWERBEATS3_BLACK: &[u8] = include_bytes!("../res/powerbeats3-black.png");
+const POWERBEATSPRO_BLACK: &[u8] = include_bytes!("../res/powerbeatspro-black.png");
+
+/* shipped images, so the binary works without the repository. The only
+ * color of a model also serves as image of the model. */
+const EMBEDDED: &[(&str, &[u8])] = &[
+    ("airpods.png", AIRPODS),
+    ("airpodspro.png", AIRPODSPRO),
+    ("airpodsmax-white.png", AIRPODSMAX_WHITE),
+    ("airpodsmax.png", AIRPODSMAX_WHITE),
+    ("beatsflex-black.png", BEATSFLEX_BLACK),
+    ("beatsflex.png", BEATSFLEX_BLACK),
+    ("beatssolo3-white.png", BEATSSOLO3_WHITE),
+    ("beatssolo3.png", BEATSSOLO3_WHITE),
+    ("beatsstudio3-black.png", BEATSSTUDIO3_BLACK),
+    ("beatsstudio3.png", BEATSSTUDIO3_BLACK),
+    ("beatsx-black.png", BEATSX_BLACK),
+    ("beatsx.png", BEATSX_BLACK),
+    ("powerbeats3-black.png", POWERBEATS3_BLACK),
+    ("powerbeats3.png", POWERBEATS3_BLACK),
+    ("powerbeatspro-black.png", POWERBEATSPRO_BLACK),
+    ("powerbeatspro.png", POWERBEATSPRO_BLACK),
+];
+
+/* maximum width and height of the loaded artwork */
+const MAX_SIZE: u32 = 256;
+
+/* icon of the icon theme if there is no artwork at all */
+const GENERIC_ICON: &str = "audio-headphones";
+
+fn model_name(model: &Model) -> Option<String> {
+    match model {
+        Model::Unknown(_) => None,
+        model => Some(format!("{:?}", model).to_lowercase()),
+    }
+}
+
+/* similar looking models */
+fn family_name(model: &Model) -> Option<&'static str> {
+    match model {
+        Model::AirPods1 | Model::AirPods2 => Some("airpods"),
+        Model::AirPods3 | Model::AirPodsPro | Model::AirPodsPro2 => Some("airpodspro"),
+        Model::AirPodsMax => Some("airpodsmax"),
+        Model::PowerbeatsPro | Model::PowerBeats3 => Some("powerbeats"),
+        Model::BeatsX | Model::BeatsFlex => Some("beatsx"),
+        Model::BeatsSolo3 | Model::BeatsSoloPro | Model::BeatsStudio3 | Model::BeatsStudioBuds => Some("beats"),
+        Model::Unknown(_) => None,
+    }
+}
+
+/* file names from the most to the least specific one,
+ * e.g. beatsflex-yellow.png, beatsflex.png, beatsx.png */
+pub fn image_names(model: &Model, color: &Color) -> Vec<String> {
+    let model_name = model_name(model);
+    let color_name = match color {
+        Color::Unknown(_) => None,
+        color => Some(format!("{:?}", color).to_lowercase()),
+    };
+    let mut names = Vec::new();
+    if let (Some(model_name), Some(color_name)) = (&model_name, &color_name) {
+        names.push(format!("{}-{}.png", model_name, color_name));
+    }
+    if let Some(model_name) = model_name {
+        names.push(format!("{}.png", model_name));
+    }
+    if let Some(family_name) = family_name(model) {
+        names.push(format!("{}.png", family_name));
+    }
+    names
+}
+
+/* Looks up the artwork in the packs and the override directory, the XDG
+ * data directories and finally in the binary. Decoded images are kept for
+ * later notifications. */
+pub struct Artwork {
+    dirs: Vec<PathBuf>,
+    images: HashMap<String, Option<RgbaImage>>,
+    /* the notification shows the battery gauges next to the artwork */
+    gauge: bool,
+}
+
+impl Artwork {
+    pub fn new(config: &ArtworkConfig) -> Artwork {
+        // $XDG_DATA_HOME/airpods-notify/artwork, $XDG_DATA_DIRS/airpods-notify/artwork
+        let data_home = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+        let data_dirs = env::var("XDG_DATA_DIRS")
+            .unwrap_or("/usr/local/share:/usr/share".to_string());
+        let dirs: Vec<PathBuf> = config.dir().into_iter()
+            .chain(data_home.into_iter()
+                .chain(data_dirs.split(':').filter(|dir| !dir.is_empty()).map(PathBuf::from))
+                .map(|dir| dir.join(APP_DIR).join("artwork")))
+            .collect();
+        // a pack is a subdirectory, e.g. artwork/<pack>/beatsflex-yellow.png
+        let dirs = config.packs.iter()
+            .flat_map(|pack| dirs.iter().map(move |dir| dir.join(pack)))
+            .chain(dirs.iter().cloned())
+            .collect();
+        Artwork {
+            dirs,
+            images: HashMap::new(),
+            gauge: config.gauge,
+        }
+    }
+
+    /* large images are scaled down, notifications show them small anyway */
+    fn load(&self, name: &str) -> Result<Option<RgbaImage>, Box<dyn Error>> {
+        let image = match self.dirs.iter().map(|dir| dir.join(name)).find(|path| path.is_file()) {
+            Some(path) => image::open(&path).map_err(|err| format!("{}: {}", path.display(), err))?,
+            None => match EMBEDDED.iter().find(|(embedded, _)| *embedded == name) {
+                Some((_, bytes)) => image::load_from_memory(bytes)?,
+                None => return Ok(None),
+            },
+        };
+        let image = if image.width() > MAX_SIZE || image.height() > MAX_SIZE {
+            image.thumbnail(MAX_SIZE, MAX_SIZE)
+        } else {
+            image
+        };
+        Ok(Some(image.to_rgba8()))
+    }
+
+    fn image(&mut self, name: &str) -> Option<RgbaImage> {
+        if !self.images.contains_key(name) {
+            let image = self.load(name).unwrap_or_else(|err| {
+                eprintln!("artwork: {}", err);
+                None
+            });
+            self.images.insert(name.to_string(), image);
+        }
+        self.images.get(name).cloned().flatten()
+    }
+
+    /* the first image of the fallback chain */
+    fn resolve(&mut self, model: &Model, color: &Color) -> Option<RgbaImage> {
+        image_names(model, color).iter().find_map(|name| self.image(name))
+    }
+
+    pub fn apply(&mut self, notification: &mut Notification, msg: &PairedMessage) {
+        // a changed image must not be sent in addition to the previous one
+        notification.hints.retain(|hint| !matches!(hint, Hint::ImageData(_)));
+        let artwork = self.resolve(&msg.model, &msg.color);
+        let image = match artwork {
+            _ if self.gauge => Some(gauge::render(artwork.as_ref(), msg)),
+            Some(artwork) => Some(artwork),
+            None => None,
+        };
+        match image.map(Image::try_from) {
+            Some(Ok(image)) => {
+                notification.hint(Hint::ImageData(image));
+            }
+            Some(Err(err)) => eprintln!("artwork: {}", err),
+            None => {
+                notification.icon(GENERIC_ICON);
+            }
+        }
+    }
+
+    /* describes the registered devices without own artwork */
+    pub fn check(&mut self, devices: &[DeviceConfig]) -> Vec<String> {
+        let mut problems = Vec::new();
+        for device in devices {
+            if self.resolve(&device.model, &device.color.unwrap_or(Color::Unknown(0))).is_none() {
+                problems.push(format!("no artwork for {} ({}), the generic icon is used", device.name, device.model));
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn only_registered_devices_are_checked() {
+        let config: Config = toml::from_str("[artwork]\ndir = \"/nonexistent\"\n\
+            [[device]]\nname = \"Work\"\nmodel = \"AirPodsPro\"\n\
+            [[device]]\nname = \"Gym\"\nmodel = \"BeatsSoloPro\"\n").unwrap();
+        let mut artwork = Artwork::new(&config.artwork);
+        assert_eq!(artwork.check(&config.device), ["no artwork for Gym (BeatsSoloPro), the generic icon is used"]);
+        assert!(artwork.check(&[]).is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+use crate::config::{AudioConfig, DeviceConfig};
+use crate::proximity::{PairedMessage, Plugged};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use tokio::process;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioProfile {
+    /* high quality playback */
+    A2dp,
+    /* playback and microphone */
+    Headset,
+}
+
+impl AudioProfile {
+    /* PipeWire and PulseAudio name the profiles differently */
+    fn names(&self) -> [&'static str; 2] {
+        match self {
+            AudioProfile::A2dp => ["a2dp-sink", "a2dp_sink"],
+            AudioProfile::Headset => ["headset-head-unit", "headset_head_unit"],
+        }
+    }
+}
+
+/* card of a Bluetooth device, e.g. bluez_card.AA_BB_CC_DD_EE_FF */
+fn card(address: &str) -> String {
+    format!("bluez_card.{}", address.replace(':', "_"))
+}
+
+/* Sound server which owns the sinks. Implemented by pactl, which works
+ * with PulseAudio and PipeWire. */
+pub trait AudioBackend {
+    async fn sinks(&mut self) -> Result<Vec<String>, Box<dyn Error>>;
+    async fn default_sink(&mut self) -> Result<String, Box<dyn Error>>;
+    async fn set_default_sink(&mut self, sink: &str) -> Result<(), Box<dyn Error>>;
+    /* active profile of the card of the device, None if there is no card */
+    async fn profile(&mut self, address: &str) -> Result<Option<String>, Box<dyn Error>>;
+    async fn set_profile(&mut self, address: &str, profile: &str) -> Result<(), Box<dyn Error>>;
+}
+
+pub struct Pactl;
+
+/* Card #<index>, followed by its indented properties */
+fn active_profile(cards: &str, address: &str) -> Option<String> {
+    let name = format!("Name: {}", card(address));
+    cards.split("Card #")
+        .find(|block| block.lines().any(|line| line.trim() == name))
+        .and_then(|block| block.lines().find_map(|line| line.trim().strip_prefix("Active Profile: ")))
+        .map(String::from)
+}
+
+impl Pactl {
+    async fn run(args: &[&str]) -> Result<String, Box<dyn Error>> {
+        let output = process::Command::new("pactl").args(args).output().await?;
+        if !output.status.success() {
+            return Err(format!("pactl {}: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()).into());
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+}
+
+impl AudioBackend for Pactl {
+    async fn sinks(&mut self) -> Result<Vec<String>, Box<dyn Error>> {
+        // <index> <name> <driver> <format> <state>
+        Ok(Pactl::run(&["list", "short", "sinks"]).await?
+            .lines()
+            .filter_map(|line| line.split('\t').nth(1))
+            .map(String::from)
+            .collect())
+    }
+
+    async fn default_sink(&mut self) -> Result<String, Box<dyn Error>> {
+        Ok(Pactl::run(&["get-default-sink"]).await?.trim().to_string())
+    }
+
+    async fn set_default_sink(&mut self, sink: &str) -> Result<(), Box<dyn Error>> {
+        Pactl::run(&["set-default-sink", sink]).await.map(|_| ())
+    }
+
+    async fn profile(&mut self, address: &str) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(active_profile(&Pactl::run(&["list", "cards"]).await?, address))
+    }
+
+    async fn set_profile(&mut self, address: &str, profile: &str) -> Result<(), Box<dyn Error>> {
+        Pactl::run(&["set-card-profile", &card(address), profile]).await.map(|_| ())
+    }
+}
+
+/* Moves the default sink to a registered headset while both buds are in
+ * ear and back to the previous sink once both are out again. The card
+ * profile of the headset is restored as well. */
+pub struct AudioSwitch<B> {
+    config: AudioConfig,
+    backend: B,
+    worn: HashMap<String, bool>,
+    /* default sink before the headset took over, with the headset sink */
+    previous: Option<(String, String)>,
+    /* profile of the headset before it was worn */
+    previous_profile: Option<String>,
+}
+
+impl<B: AudioBackend> AudioSwitch<B> {
+    pub fn new(config: AudioConfig, backend: B) -> AudioSwitch<B> {
+        AudioSwitch {
+            config,
+            backend,
+            worn: HashMap::new(),
+            previous: None,
+            previous_profile: None,
+        }
+    }
+
+    /* sink of the connected headset, e.g. bluez_output.AA_BB_CC_DD_EE_FF.1 */
+    async fn headset_sink(&mut self, address: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let address = address.replace(':', "_").to_uppercase();
+        Ok(self.backend.sinks().await?
+            .into_iter()
+            .find(|sink| sink.starts_with("bluez_") && sink.to_uppercase().contains(&address)))
+    }
+
+    pub async fn update(&mut self, device: &DeviceConfig, msg: &PairedMessage) -> Result<(), Box<dyn Error>> {
+        let address = match (&device.address, self.config.switch_sink) {
+            (Some(address), true) => address,
+            _ => return Ok(()),
+        };
+        // the case lid does not matter, the buds report whether they are in ear;
+        // a single bud taken out keeps the headset, e.g. for a call
+        let worn = match msg.plugged_in_ear {
+            Plugged::Both => true,
+            Plugged::None => false,
+            Plugged::Single => return Ok(()),
+        };
+        // only a change is a real event, not the repeated advertisement
+        if self.worn.insert(device.name.clone(), worn) == Some(worn) {
+            return Ok(());
+        }
+        if worn {
+            self.switch_to(address).await
+        } else {
+            self.restore(address).await
+        }
+    }
+
+    async fn switch_to(&mut self, address: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(profile) = self.config.profile {
+            self.set_profile(address, profile).await?;
+        }
+        // the headset is not connected to this machine
+        let sink = match self.headset_sink(address).await? {
+            Some(sink) => sink,
+            None => return Ok(()),
+        };
+        let current = self.backend.default_sink().await?;
+        if current != sink {
+            self.backend.set_default_sink(&sink).await?;
+            self.previous = Some((current, sink));
+        }
+        Ok(())
+    }
+
+    /* either name of the profile, the previous one is kept for the restore */
+    async fn set_profile(&mut self, address: &str, profile: AudioProfile) -> Result<(), Box<dyn Error>> {
+        let names = profile.names();
+        let previous = match self.backend.profile(address).await? {
+            Some(previous) if names.contains(&previous.as_str()) => return Ok(()),
+            previous => previous,
+        };
+        if let Err(err) = self.backend.set_profile(address, names[0]).await {
+            self.backend.set_profile(address, names[1]).await.map_err(|_| err)?;
+        }
+        self.previous_profile = previous;
+        Ok(())
+    }
+
+    /* the previous sink is not restored if the user changed the default meanwhile */
+    async fn restore(&mut self, address: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(profile) = self.previous_profile.take() {
+            self.backend.set_profile(address, &profile).await?;
+        }
+        if let Some((previous, sink)) = self.previous.take() {
+            if self.backend.default_sink().await? == sink {
+                self.backend.set_default_sink(&previous).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proximity::Lid;
+    use crate::testing::{paired_message, RecordingBackend, HEADSET, SPEAKERS};
+
+    const ADDRESS: &str = "AA:BB:CC:DD:EE:01";
+
+    fn switch(config: AudioConfig) -> AudioSwitch<RecordingBackend> {
+        AudioSwitch::new(config, RecordingBackend::new(&[SPEAKERS, HEADSET]))
+    }
+
+    fn device() -> DeviceConfig {
+        toml::from_str::<crate::config::Config>(&format!(
+            "[[device]]\nname = \"Work\"\nmodel = \"AirPodsPro\"\naddress = \"{}\"\n", ADDRESS
+        )).unwrap().device.remove(0)
+    }
+
+    fn report(plugged_in_ear: Plugged, lid: Lid) -> PairedMessage {
+        PairedMessage { plugged_in_ear, lid, ..paired_message(5, 5, 5) }
+    }
+
+    #[tokio::test]
+    async fn worn_headset_becomes_the_default() {
+        let mut audio = switch(AudioConfig::default());
+        let device = device();
+        audio.update(&device, &report(Plugged::None, Lid::Open(1))).await.unwrap();
+        // the lid is closed while the buds are worn
+        audio.update(&device, &report(Plugged::Both, Lid::Closed(1))).await.unwrap();
+        audio.update(&device, &report(Plugged::Both, Lid::Closed(2))).await.unwrap();
+        assert_eq!(audio.backend.calls.take(), [format!("default {}", HEADSET)]);
+
+        // one bud taken out keeps the headset
+        audio.update(&device, &report(Plugged::Single, Lid::Closed(2))).await.unwrap();
+        audio.update(&device, &report(Plugged::Both, Lid::Closed(2))).await.unwrap();
+        assert!(audio.backend.calls.take().is_empty());
+
+        audio.update(&device, &report(Plugged::None, Lid::Open(2))).await.unwrap();
+        assert_eq!(audio.backend.calls.take(), [format!("default {}", SPEAKERS)]);
+    }
+
+    #[tokio::test]
+    async fn default_changed_by_the_user_is_kept() {
+        let config = AudioConfig { profile: Some(AudioProfile::A2dp), ..AudioConfig::default() };
+        let mut audio = switch(config);
+        let device = device();
+        audio.update(&device, &report(Plugged::Both, Lid::Open(1))).await.unwrap();
+        audio.backend.default = "hdmi".to_string();
+        audio.update(&device, &report(Plugged::None, Lid::Open(1))).await.unwrap();
+        assert_eq!(audio.backend.calls.take(), [format!("profile {} a2dp-sink", ADDRESS), format!("default {}", HEADSET)]);
+        assert_eq!(audio.backend.default, "hdmi");
+    }
+
+    #[tokio::test]
+    async fn profile_is_restored_when_the_buds_are_out() {
+        let config = AudioConfig { profile: Some(AudioProfile::Headset), ..AudioConfig::default() };
+        let mut audio = switch(config);
+        audio.backend.profile = Some("a2dp-sink".to_string());
+        let device = device();
+        audio.update(&device, &report(Plugged::Both, Lid::Open(1))).await.unwrap();
+        assert_eq!(audio.backend.calls.take(), [format!("profile {} headset-head-unit", ADDRESS), format!("default {}", HEADSET)]);
+
+        audio.update(&device, &report(Plugged::None, Lid::Open(1))).await.unwrap();
+        assert_eq!(audio.backend.calls.take(), [format!("profile {} a2dp-sink", ADDRESS), format!("default {}", SPEAKERS)]);
+        assert_eq!(audio.backend.profile.as_deref(), Some("a2dp-sink"));
+    }
+
+    #[test]
+    fn active_profile_is_read_from_the_cards() {
+        let cards = "Card #42\n\tName: alsa_card.pci-0000_00_1f.3\n\tActive Profile: output:analog-stereo\n\
+            Card #43\n\tName: bluez_card.AA_BB_CC_DD_EE_01\n\tDriver: module-bluez5-device.c\n\tActive Profile: a2dp-sink\n";
+        assert_eq!(active_profile(cards, ADDRESS).as_deref(), Some("a2dp-sink"));
+        assert_eq!(active_profile(cards, "AA:BB:CC:DD:EE:02"), None);
+    }
+
+    #[tokio::test]
+    async fn nothing_changes_without_headset_sink() {
+        let mut audio = switch(AudioConfig::default());
+        audio.backend.sinks.truncate(1);
+        let device = device();
+        audio.update(&device, &report(Plugged::Both, Lid::Open(1))).await.unwrap();
+        audio.update(&device, &report(Plugged::None, Lid::Open(1))).await.unwrap();
+        assert!(audio.backend.calls.take().is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+use crate::config::{AutoConnectConfig, DeviceConfig};
+use crate::identity::Identity;
+use crate::proximity::{PairedMessage, Lid, Plugged};
+use std::collections::HashMap;
+use std::time::Instant;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Connect { address: String },
+    Disconnect { address: String },
+}
+
+#[derive(Default)]
+struct State {
+    lid: Option<Lid>,
+    /* a disconnect right after a connect is not held back and vice versa */
+    last_connect: Option<Instant>,
+    last_disconnect: Option<Instant>,
+}
+
+/* Connects registered devices when their lid opens near the machine and
+ * disconnects them when both buds are put back into the closed case, so
+ * the phone can take over. */
+pub struct AutoConnect {
+    config: AutoConnectConfig,
+    devices: HashMap<String, State>,
+}
+
+impl AutoConnect {
+    pub fn new(config: AutoConnectConfig) -> AutoConnect {
+        AutoConnect {
+            config,
+            devices: HashMap::new(),
+        }
+    }
+
+    /* only reports resolved to the bonded device by its IRK are followed,
+     * identical devices of other people must not trigger anything */
+    pub fn update(&mut self, device: &DeviceConfig, identity: Option<&Identity>, msg: &PairedMessage, nearby: bool) -> Option<Command> {
+        let address = match (&device.address, device.auto_connect) {
+            (Some(address), true) => address.clone(),
+            _ => return None,
+        };
+        if !identity.is_some_and(|identity| identity.resolved && device.has_address(&identity.address)) {
+            return None;
+        }
+        let state = self.devices.entry(device.name.clone()).or_default();
+        let previous = state.lid.replace(msg.lid);
+
+        // only a change of the lid is a real event, not the repeated advertisement
+        let (command, last) = match (previous, msg.lid) {
+            (Some(Lid::Closed(_)), Lid::Open(_)) if nearby => (Command::Connect { address }, &mut state.last_connect),
+            (Some(Lid::Open(_)), Lid::Closed(_)) if msg.plugged_in_case == Plugged::Both => {
+                (Command::Disconnect { address }, &mut state.last_disconnect)
+            }
+            _ => return None,
+        };
+        if last.is_some_and(|at| at.elapsed() < self.config.cooldown()) {
+            return None;
+        }
+        *last = Some(Instant::now());
+        Some(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::paired_message;
+
+    const ADDRESS: &str = "AA:BB:CC:DD:EE:01";
+
+    fn device() -> DeviceConfig {
+        toml::from_str::<crate::config::Config>(&format!(
+            "[[device]]\nname = \"Work\"\nmodel = \"AirPodsPro\"\naddress = \"{}\"\nauto_connect = true\n", ADDRESS
+        )).unwrap().device.remove(0)
+    }
+
+    fn identity(resolved: bool) -> Identity {
+        Identity { alias: "Work AirPods".to_string(), address: ADDRESS.to_string(), connected: false, resolved }
+    }
+
+    fn lid(open: bool) -> PairedMessage {
+        PairedMessage {
+            lid: if open { Lid::Open(1) } else { Lid::Closed(1) },
+            plugged_in_case: Plugged::Both,
+            ..paired_message(5, 5, 5)
+        }
+    }
+
+    #[test]
+    fn lid_changes_connect_and_disconnect() {
+        let mut auto_connect = AutoConnect::new(AutoConnectConfig::default());
+        let (device, identity) = (device(), identity(true));
+        let connect = Command::Connect { address: ADDRESS.to_string() };
+        let disconnect = Command::Disconnect { address: ADDRESS.to_string() };
+        assert_eq!(auto_connect.update(&device, Some(&identity), &lid(false), true), None);
+        assert_eq!(auto_connect.update(&device, Some(&identity), &lid(true), true), Some(connect.clone()));
+        assert_eq!(auto_connect.update(&device, Some(&identity), &lid(true), true), None);
+        // the cooldown of the connect does not hold back the disconnect
+        assert_eq!(auto_connect.update(&device, Some(&identity), &lid(false), true), Some(disconnect));
+        // but the next connect within the cooldown
+        assert_eq!(auto_connect.update(&device, Some(&identity), &lid(true), true), None);
+    }
+
+    #[test]
+    fn only_the_resolved_device_triggers() {
+        let mut auto_connect = AutoConnect::new(AutoConnectConfig::default());
+        let device = device();
+        let other = Identity { address: "AA:BB:CC:DD:EE:02".to_string(), ..identity(true) };
+        for identity in [None, Some(identity(false)), Some(other)] {
+            assert_eq!(auto_connect.update(&device, identity.as_ref(), &lid(false), true), None);
+            assert_eq!(auto_connect.update(&device, identity.as_ref(), &lid(true), true), None);
+        }
+    }
+}
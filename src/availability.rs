@@ -0,0 +1,155 @@
+use crate::logind::Logind;
+use std::fmt;
+use tokio::process;
+use zbus::{proxy, Connection};
+
+/* reason why the user does not want to be disturbed */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Away {
+    DoNotDisturb,
+    Locked,
+    Idle,
+    QuietHours,
+}
+
+impl fmt::Display for Away {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/* KDE Plasma, also set while a fullscreen application inhibits notifications */
+#[proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[zbus(property)]
+    fn inhibited(&self) -> zbus::Result<bool>;
+}
+
+#[proxy(
+    interface = "org.dunstproject.cmd0",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Dunst {
+    #[zbus(property)]
+    fn paused(&self) -> zbus::Result<bool>;
+}
+
+#[proxy(
+    interface = "fr.emersion.Mako",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/fr/emersion/Mako"
+)]
+trait Mako {
+    fn list_modes(&self) -> zbus::Result<Vec<String>>;
+}
+
+/* Asks the notification server, the session lock and the idle hint of
+ * logind whether the user is available. The session connection is
+ * injected, so the checks work the same way on a private bus. */
+pub struct Availability {
+    connection: Connection,
+    logind: Option<Logind>,
+    /* GNOME is asked through gsettings, outside of the connection */
+    gsettings: bool,
+}
+
+impl Availability {
+    pub fn new(connection: Connection, logind: Option<Logind>) -> Availability {
+        Availability { connection, logind, gsettings: true }
+    }
+
+    pub async fn session(logind: Option<Logind>) -> zbus::Result<Availability> {
+        Ok(Availability::new(Connection::session().await?, logind))
+    }
+
+    /* each server only implements its own interface, the others fail */
+    async fn do_not_disturb(&self) -> bool {
+        if let Ok(proxy) = NotificationsProxy::new(&self.connection).await {
+            if proxy.inhibited().await.unwrap_or(false) {
+                return true;
+            }
+        }
+        if let Ok(proxy) = DunstProxy::new(&self.connection).await {
+            if proxy.paused().await.unwrap_or(false) {
+                return true;
+            }
+        }
+        if let Ok(proxy) = MakoProxy::new(&self.connection).await {
+            if proxy.list_modes().await.is_ok_and(|modes| modes.iter().any(|mode| mode == "do-not-disturb")) {
+                return true;
+            }
+        }
+        self.gsettings && gnome_do_not_disturb().await
+    }
+
+    pub async fn away(&self) -> Option<Away> {
+        if self.do_not_disturb().await {
+            return Some(Away::DoNotDisturb);
+        }
+        let logind = self.logind.as_ref()?;
+        if logind.is_locked().await.unwrap_or(false) {
+            return Some(Away::Locked);
+        }
+        if logind.is_idle().await.unwrap_or(false) {
+            return Some(Away::Idle);
+        }
+        None
+    }
+}
+
+/* GNOME Shell keeps the state in GSettings only */
+async fn gnome_do_not_disturb() -> bool {
+    process::Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.notifications", "show-banners"])
+        .output()
+        .await
+        .is_ok_and(|output| output.status.success() && output.stdout.starts_with(b"false"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FakeDoNotDisturb, FakeLogind, PrivateBus};
+
+    /* the fakes do not signal their changes to cached properties */
+    async fn away(bus: &PrivateBus, logind: bool) -> Option<Away> {
+        let logind = match logind {
+            true => Some(Logind::new(&bus.connect().await).await.unwrap()),
+            false => None,
+        };
+        Availability { connection: bus.connect().await, logind, gsettings: false }.away().await
+    }
+
+    #[tokio::test]
+    async fn do_not_disturb_goes_before_lock_and_idle() {
+        let bus = PrivateBus::start();
+        let server = FakeDoNotDisturb::start(&bus).await;
+        let logind = FakeLogind::start(&bus).await;
+        assert_eq!(away(&bus, true).await, None);
+
+        logind.set_idle(true).await;
+        assert_eq!(away(&bus, true).await, Some(Away::Idle));
+        logind.set_locked(true).await;
+        assert_eq!(away(&bus, true).await, Some(Away::Locked));
+        server.set_paused(true).await;
+        assert_eq!(away(&bus, true).await, Some(Away::DoNotDisturb));
+
+        server.set_paused(false).await;
+        server.set_inhibited(true).await;
+        assert_eq!(away(&bus, true).await, Some(Away::DoNotDisturb));
+    }
+
+    #[tokio::test]
+    async fn without_logind_only_do_not_disturb_counts() {
+        let bus = PrivateBus::start();
+        let server = FakeDoNotDisturb::start(&bus).await;
+        assert_eq!(away(&bus, false).await, None);
+        server.set_inhibited(true).await;
+        assert_eq!(away(&bus, false).await, Some(Away::DoNotDisturb));
+    }
+}
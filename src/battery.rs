@@ -0,0 +1,272 @@
+use crate::config::{Config, DeviceConfig};
+use crate::proximity::{PairedMessage, Model, Battery};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+const HOUR: u64 = 60 * 60;
+
+/* proximity reports are only sent while the device is nearby and awake */
+const PROXIMITY_MAX_AGE: Duration = Duration::from_secs(10 * 60);
+/* BlueZ values are refreshed by polling while the device is connected */
+const BLUEZ_MAX_AGE: Duration = Duration::from_secs(2 * 60);
+
+/* talk time of a fully charged device as stated by the manufacturer */
+fn rated_talk_time(model: &Model) -> Option<Duration> {
+    let minutes = match model {
+        Model::AirPods1 => 120,
+        Model::AirPods2 => 180,
+        Model::AirPods3 => 240,
+        Model::AirPodsPro => 210,
+        Model::AirPodsPro2 => 270,
+        Model::AirPodsMax => 20 * 60,
+        Model::PowerbeatsPro => 9 * 60,
+        Model::BeatsX => 8 * 60,
+        Model::BeatsFlex => 12 * 60,
+        Model::BeatsSolo3 => 40 * 60,
+        Model::BeatsStudio3 => 22 * 60,
+        Model::PowerBeats3 => 12 * 60,
+        Model::BeatsStudioBuds => 5 * 60,
+        Model::BeatsSoloPro => 22 * 60,
+        Model::Unknown(_) => return None,
+    };
+    Some(Duration::from_secs(minutes * 60))
+}
+
+/* estimated remaining talk time of a device at the given level. */
+pub fn remaining_talk_time(model: &Model, percent: u8) -> Option<Duration> {
+    let rated = rated_talk_time(model)?;
+    Some(rated * u32::from(percent.min(100)) / 100)
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    if duration.as_secs() >= HOUR {
+        format!("{}h {:02}min", minutes / 60, minutes % 60)
+    } else {
+        format!("{}min", minutes)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    /* BLE proximity advertisement, 10% steps */
+    Proximity,
+    /* org.bluez.Battery1 of the connected device, 1% steps */
+    BlueZ,
+}
+
+impl Source {
+    fn max_age(&self) -> Duration {
+        match self {
+            Source::Proximity => PROXIMITY_MAX_AGE,
+            Source::BlueZ => BLUEZ_MAX_AGE,
+        }
+    }
+
+    /* resolution of the reported value in percent */
+    fn step(&self) -> u8 {
+        match self {
+            Source::Proximity => 10,
+            Source::BlueZ => 1,
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reading {
+    pub percent: u8,
+    pub source: Source,
+    pub at: Instant,
+}
+
+impl Reading {
+    fn from_battery(battery: Battery, at: Instant) -> Option<Reading> {
+        match battery {
+            Battery::Level(value) => Some(Reading {
+                percent: value.min(10) * 10,
+                source: Source::Proximity,
+                at,
+            }),
+            Battery::None => None,
+        }
+    }
+
+    pub fn age(&self) -> Duration {
+        self.at.elapsed()
+    }
+
+    pub fn is_fresh(&self) -> bool {
+        self.age() <= self.source.max_age()
+    }
+}
+
+impl fmt::Display for Reading {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}%", self.percent)
+    }
+}
+
+/* Battery status of a single device merged from all sources. Each source
+ * keeps the time of its last update, so stale values can be told apart. */
+#[derive(Debug, Clone)]
+pub struct DeviceBattery {
+    pub model: Model,
+    proximity: Option<(PairedMessage, Instant)>,
+    bluez: Option<Reading>,
+}
+
+impl DeviceBattery {
+    pub fn new(model: Model) -> DeviceBattery {
+        DeviceBattery {
+            model,
+            proximity: None,
+            bluez: None,
+        }
+    }
+
+    pub fn update_proximity(&mut self, msg: &PairedMessage) {
+        self.proximity = Some((*msg, Instant::now()));
+    }
+
+    pub fn update_bluez(&mut self, percent: u8) {
+        self.bluez = Some(Reading {
+            percent,
+            source: Source::BlueZ,
+            at: Instant::now(),
+        });
+    }
+
+    fn component(&self, battery: impl Fn(&PairedMessage) -> Battery) -> Option<Reading> {
+        self.proximity.as_ref()
+            .and_then(|(msg, at)| Reading::from_battery(battery(msg), *at))
+    }
+
+    pub fn left(&self) -> Option<Reading> {
+        self.component(|msg| msg.left_battery_level)
+    }
+
+    pub fn right(&self) -> Option<Reading> {
+        self.component(|msg| msg.right_battery_level)
+    }
+
+    /* level of the worn parts according to the proximity reports only */
+    pub fn proximity_headset(&self) -> Option<Reading> {
+        [self.left(), self.right()].into_iter()
+            .flatten()
+            .min_by_key(|reading| reading.percent)
+    }
+
+    /* Level of the worn parts. Fresh values are preferred over stale ones
+     * and precise values over coarse ones, otherwise the newest one wins. */
+    pub fn headset(&self) -> Option<Reading> {
+        [self.bluez, self.proximity_headset()].into_iter()
+            .flatten()
+            .max_by_key(|reading| (reading.is_fresh(), u8::MAX - reading.source.step(), reading.at))
+    }
+
+    pub fn remaining_talk_time(&self) -> Option<Duration> {
+        remaining_talk_time(&self.model, self.headset()?.percent)
+    }
+}
+
+/* Battery status of the registered devices, keyed by device name */
+pub struct BatteryModel {
+    devices: HashMap<String, DeviceBattery>,
+    /* nearby headsets while no device is registered, keyed by model and color */
+    unregistered: HashMap<String, DeviceBattery>,
+}
+
+impl BatteryModel {
+    pub fn new(config: &Config) -> BatteryModel {
+        BatteryModel {
+            devices: config.device.iter()
+                .map(|device| (device.name.clone(), DeviceBattery::new(device.model)))
+                .collect(),
+            unregistered: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, device: &DeviceConfig) -> Option<&DeviceBattery> {
+        self.devices.get(&device.name)
+    }
+
+    pub fn update_proximity(&mut self, device: &DeviceConfig, msg: &PairedMessage) {
+        if let Some(battery) = self.devices.get_mut(&device.name) {
+            battery.update_proximity(msg);
+        }
+    }
+
+    pub fn update_bluez(&mut self, device: &DeviceConfig, percent: u8) {
+        if let Some(battery) = self.devices.get_mut(&device.name) {
+            battery.update_bluez(percent);
+        }
+    }
+
+    pub fn update_unregistered(&mut self, msg: &PairedMessage) {
+        self.unregistered.entry(format!("{} {}", msg.model, msg.color))
+            .or_insert_with(|| DeviceBattery::new(msg.model))
+            .update_proximity(msg);
+    }
+
+    /* the nearby headset with the lowest current level, the safe guess without a registered device */
+    pub fn lowest_unregistered(&self) -> Option<&DeviceBattery> {
+        self.unregistered.values()
+            .filter_map(|battery| Some((battery.headset().filter(Reading::is_fresh)?, battery)))
+            .min_by_key(|(reading, _)| reading.percent)
+            .map(|(_, battery)| battery)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proximity::Color;
+    use crate::testing::paired_message;
+
+    #[test]
+    fn fresh_bluez_level_is_preferred_over_proximity() {
+        let mut battery = DeviceBattery::new(Model::AirPodsPro);
+        battery.update_proximity(&paired_message(8, 6, 10));
+        assert_eq!(battery.headset().map(|reading| (reading.percent, reading.source)), Some((60, Source::Proximity)));
+
+        battery.update_bluez(73);
+        assert_eq!(battery.headset().map(|reading| (reading.percent, reading.source)), Some((73, Source::BlueZ)));
+        // the buds keep their own detail
+        assert_eq!(battery.left().map(|reading| reading.percent), Some(80));
+        assert_eq!(battery.right().map(|reading| reading.percent), Some(60));
+    }
+
+    #[test]
+    fn stale_bluez_level_loses_against_fresh_proximity() {
+        // the monotonic clock may have started too recently to go back that far
+        let Some(at) = Instant::now().checked_sub(BLUEZ_MAX_AGE + Duration::from_secs(1)) else {
+            return;
+        };
+        let mut battery = DeviceBattery::new(Model::AirPodsPro);
+        battery.bluez = Some(Reading {
+            percent: 73,
+            source: Source::BlueZ,
+            at,
+        });
+        battery.update_proximity(&paired_message(5, 5, 10));
+        assert_eq!(battery.headset().map(|reading| (reading.percent, reading.source)), Some((50, Source::Proximity)));
+        assert_eq!(battery.remaining_talk_time(), Some(Duration::from_secs(105 * 60)));
+    }
+
+    #[test]
+    fn lowest_unregistered_headset() {
+        let mut batteries = BatteryModel::new(&Config::default());
+        assert!(batteries.lowest_unregistered().is_none());
+        batteries.update_unregistered(&paired_message(9, 9, 10));
+        batteries.update_unregistered(&PairedMessage { color: Color::Black, ..paired_message(3, 4, 10) });
+        let lowest = batteries.lowest_unregistered().and_then(|battery| battery.headset());
+        assert_eq!(lowest.map(|reading| reading.percent), Some(30));
+    }
+}
@@ -0,0 +1,527 @@
+use crate::proximity::Model;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use zbus::fdo::ObjectManagerProxy;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue};
+use zbus::{interface, proxy, Connection};
+
+const BLUEZ_SERVICE: &str = "org.bluez";
+const ADAPTER_INTERFACE: &str = "org.bluez.Adapter1";
+const DEVICE_INTERFACE: &str = "org.bluez.Device1";
+const BATTERY_INTERFACE: &str = "org.bluez.Battery1";
+const AGENT_PATH: &str = "/airpods_notify/agent";
+const PROVIDER_PATH: &str = "/airpods_notify/battery";
+/* source of the published batteries, as shown by BlueZ */
+pub const PROVIDER_SOURCE: &str = "airpods-notify";
+const APPLE_VENDOR: &str = "004C";
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(20);
+const DISCOVERY_POLL: Duration = Duration::from_millis(500);
+
+#[proxy(interface = "org.bluez.Adapter1", default_service = "org.bluez")]
+trait Adapter1 {
+    fn start_discovery(&self) -> zbus::Result<()>;
+    fn stop_discovery(&self) -> zbus::Result<()>;
+}
+
+#[proxy(interface = "org.bluez.AgentManager1", default_service = "org.bluez", default_path = "/org/bluez")]
+trait AgentManager1 {
+    fn register_agent(&self, agent: &ObjectPath<'_>, capability: &str) -> zbus::Result<()>;
+    fn unregister_agent(&self, agent: &ObjectPath<'_>) -> zbus::Result<()>;
+}
+
+#[proxy(interface = "org.bluez.Device1", default_service = "org.bluez")]
+trait Device1 {
+    fn pair(&self) -> zbus::Result<()>;
+    fn connect(&self) -> zbus::Result<()>;
+    fn disconnect(&self) -> zbus::Result<()>;
+    #[zbus(property)]
+    fn set_trusted(&self, trusted: bool) -> zbus::Result<()>;
+}
+
+#[proxy(interface = "org.bluez.BatteryProviderManager1", default_service = "org.bluez")]
+trait BatteryProviderManager1 {
+    fn register_battery_provider(&self, provider: &ObjectPath<'_>) -> zbus::Result<()>;
+}
+
+#[derive(Debug, zbus::DBusError)]
+#[zbus(prefix = "org.bluez.Error")]
+enum AgentError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    Rejected(String),
+}
+
+/* Agent for the pairing of a single device. Headsets pair without user
+ * interaction, requests for other devices are rejected. */
+struct Agent {
+    device: OwnedObjectPath,
+}
+
+impl Agent {
+    fn accept(&self, device: &ObjectPath<'_>) -> Result<(), AgentError> {
+        if *device == *self.device {
+            Ok(())
+        } else {
+            Err(AgentError::Rejected(format!("{} is not being paired", device)))
+        }
+    }
+}
+
+#[interface(name = "org.bluez.Agent1")]
+impl Agent {
+    fn release(&self) {}
+
+    fn request_pin_code(&self, _device: ObjectPath<'_>) -> Result<String, AgentError> {
+        Err(AgentError::Rejected("PIN codes are not supported".to_string()))
+    }
+
+    fn display_pin_code(&self, _device: ObjectPath<'_>, _pincode: String) {}
+
+    fn request_passkey(&self, _device: ObjectPath<'_>) -> Result<u32, AgentError> {
+        Err(AgentError::Rejected("passkeys are not supported".to_string()))
+    }
+
+    fn display_passkey(&self, _device: ObjectPath<'_>, _passkey: u32, _entered: u16) {}
+
+    fn request_confirmation(&self, device: ObjectPath<'_>, _passkey: u32) -> Result<(), AgentError> {
+        self.accept(&device)
+    }
+
+    fn request_authorization(&self, device: ObjectPath<'_>) -> Result<(), AgentError> {
+        self.accept(&device)
+    }
+
+    fn authorize_service(&self, device: ObjectPath<'_>, _uuid: String) -> Result<(), AgentError> {
+        self.accept(&device)
+    }
+
+    fn cancel(&self) {}
+}
+
+/* battery of a BlueZ device, published through the battery provider */
+struct ProvidedBattery {
+    device: OwnedObjectPath,
+    percentage: u8,
+}
+
+#[interface(name = "org.bluez.BatteryProvider1")]
+impl ProvidedBattery {
+    #[zbus(property)]
+    fn percentage(&self) -> u8 {
+        self.percentage
+    }
+
+    #[zbus(property)]
+    fn source(&self) -> &str {
+        PROVIDER_SOURCE
+    }
+
+    #[zbus(property)]
+    fn device(&self) -> ObjectPath<'_> {
+        self.device.as_ref()
+    }
+}
+
+/* Publishes battery levels of BlueZ devices, so that desktop shells and
+ * UPower show them like the level of any other Bluetooth device. */
+pub struct BatteryProvider {
+    connection: Connection,
+    /* published battery object by BlueZ device */
+    batteries: HashMap<OwnedObjectPath, OwnedObjectPath>,
+}
+
+impl BatteryProvider {
+    pub async fn register(bluez: &BlueZ) -> zbus::Result<BatteryProvider> {
+        let connection = bluez.connection.clone();
+        connection.object_server().at(PROVIDER_PATH, zbus::fdo::ObjectManager).await?;
+        let manager = BatteryProviderManager1Proxy::builder(&connection)
+            .path(bluez.adapter_path().await?)?
+            .build()
+            .await?;
+        manager.register_battery_provider(&ObjectPath::try_from(PROVIDER_PATH)?).await?;
+        Ok(BatteryProvider {
+            connection,
+            batteries: HashMap::new(),
+        })
+    }
+
+    pub async fn publish(&mut self, device: &OwnedObjectPath, percentage: u8) -> zbus::Result<()> {
+        let server = self.connection.object_server();
+        match self.batteries.get(device) {
+            Some(path) => {
+                let battery = server.interface::<_, ProvidedBattery>(path).await?;
+                let mut battery_mut = battery.get_mut().await;
+                if battery_mut.percentage != percentage {
+                    battery_mut.percentage = percentage;
+                    battery_mut.percentage_changed(battery.signal_emitter()).await?;
+                }
+            }
+            None => {
+                // e.g. /org/bluez/hci0/dev_AA_BB_CC_DD_EE_FF -> /airpods_notify/battery/dev_AA_BB_CC_DD_EE_FF
+                let name = device.as_str().rsplit('/').next().unwrap_or_default();
+                let path = OwnedObjectPath::try_from(format!("{}/{}", PROVIDER_PATH, name))?;
+                server.at(&path, ProvidedBattery { device: device.clone(), percentage }).await?;
+                self.batteries.insert(device.clone(), path);
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn remove(&mut self, device: &OwnedObjectPath) -> zbus::Result<()> {
+        if let Some(path) = self.batteries.remove(device) {
+            self.connection.object_server().remove::<ProvidedBattery, _>(&path).await?;
+        }
+        Ok(())
+    }
+
+    pub fn is_published(&self, device: &OwnedObjectPath) -> bool {
+        self.batteries.contains_key(device)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PairingStep {
+    Discovering,
+    Pairing,
+    Trusting,
+    Connecting,
+    Connected,
+}
+
+impl fmt::Display for PairingStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PairingStep::Discovering => write!(f, "Searching for the device…"),
+            PairingStep::Pairing => write!(f, "Pairing…"),
+            PairingStep::Trusting => write!(f, "Trusting…"),
+            PairingStep::Connecting => write!(f, "Connecting…"),
+            PairingStep::Connected => write!(f, "Connected"),
+        }
+    }
+}
+
+/* BlueZ device object as seen on the system bus */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub path: OwnedObjectPath,
+    pub address: String,
+    pub alias: Option<String>,
+    pub paired: bool,
+    pub connected: bool,
+    /* org.bluez.Battery1 percentage, reported via HFP while connected */
+    pub battery: Option<u8>,
+    /* origin of the battery, if it is published by a battery provider */
+    pub battery_source: Option<String>,
+    /* Device ID profile, e.g. bluetooth:v004Cp200Ed0A10 */
+    pub modalias: Option<String>,
+}
+
+impl Device {
+    /* model of an Apple device according to its product id */
+    pub fn model(&self) -> Option<Model> {
+        let ids = self.modalias.as_deref()?.split_once(":v")?.1;
+        let (vendor, product) = ids.get(..4).zip(ids.get(5..9))?;
+        if !vendor.eq_ignore_ascii_case(APPLE_VENDOR) {
+            return None;
+        }
+        u16::from_str_radix(product, 16).ok().map(Model::from_product_id)
+    }
+
+    pub fn name(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.address)
+    }
+}
+
+/* Client for the org.bluez service. The connection is injected, so the
+ * client works the same way with the system bus or a private bus. */
+#[derive(Clone)]
+pub struct BlueZ {
+    connection: Connection,
+    /* BlueZ accepts a single agent per connection, so pairings take turns */
+    pairing: Arc<Mutex<()>>,
+}
+
+impl BlueZ {
+    pub fn new(connection: Connection) -> BlueZ {
+        BlueZ { connection, pairing: Arc::default() }
+    }
+
+    pub async fn system() -> zbus::Result<BlueZ> {
+        Ok(BlueZ::new(Connection::system().await?))
+    }
+
+    async fn objects(&self) -> zbus::Result<zbus::fdo::ManagedObjects> {
+        let manager = ObjectManagerProxy::builder(&self.connection)
+            .destination(BLUEZ_SERVICE)?
+            .path("/")?
+            .build()
+            .await?;
+        Ok(manager.get_managed_objects().await?)
+    }
+
+    async fn adapter_path(&self) -> zbus::Result<OwnedObjectPath> {
+        self.objects().await?
+            .into_iter()
+            .find(|(_, interfaces)| interfaces.keys().any(|name| name.as_str() == ADAPTER_INTERFACE))
+            .map(|(path, _)| path)
+            .ok_or_else(|| zbus::Error::Failure("no Bluetooth adapter".to_string()))
+    }
+
+    async fn adapter(&self) -> zbus::Result<Adapter1Proxy<'static>> {
+        Adapter1Proxy::builder(&self.connection).path(self.adapter_path().await?)?.build().await
+    }
+
+    pub async fn device(&self, address: &str) -> zbus::Result<Option<Device>> {
+        Ok(self.devices().await?
+            .into_iter()
+            .find(|device| device.address.eq_ignore_ascii_case(address)))
+    }
+
+    /* discovers the device if BlueZ does not know it yet */
+    async fn discover(&self, address: &str) -> zbus::Result<Device> {
+        if let Some(device) = self.device(address).await? {
+            return Ok(device);
+        }
+        let adapter = self.adapter().await?;
+        adapter.start_discovery().await?;
+        let start = Instant::now();
+        let device = loop {
+            // discovery is stopped on errors as well
+            match self.device(address).await {
+                Ok(Some(device)) => break Ok(device),
+                Ok(None) => (),
+                Err(err) => break Err(err),
+            }
+            if start.elapsed() >= DISCOVERY_TIMEOUT {
+                break Err(zbus::Error::Failure(format!("{} was not found", address)));
+            }
+            tokio::time::sleep(DISCOVERY_POLL).await;
+        };
+        let _ = adapter.stop_discovery().await;
+        device
+    }
+
+    /* Pairs, trusts and connects the device with the given address. The
+     * progress is reported after each step. */
+    pub async fn pair_and_connect<F>(&self, address: &str, mut progress: F) -> zbus::Result<()>
+    where
+        F: FnMut(PairingStep),
+    {
+        progress(PairingStep::Discovering);
+        let device = self.discover(address).await?;
+        let proxy = Device1Proxy::builder(&self.connection)
+            .path(device.path.clone())?
+            .build()
+            .await?;
+
+        if !device.paired {
+            progress(PairingStep::Pairing);
+            let _turn = self.pairing.lock().await;
+            let agent_path = ObjectPath::try_from(AGENT_PATH)?;
+            let agent_manager = AgentManager1Proxy::new(&self.connection).await?;
+            self.connection.object_server()
+                .at(AGENT_PATH, Agent { device: device.path.clone() })
+                .await?;
+            let paired = match agent_manager.register_agent(&agent_path, "NoInputNoOutput").await {
+                Ok(()) => {
+                    let paired = proxy.pair().await;
+                    let _ = agent_manager.unregister_agent(&agent_path).await;
+                    paired
+                }
+                Err(err) => Err(err),
+            };
+            self.connection.object_server().remove::<Agent, _>(AGENT_PATH).await?;
+            paired?;
+        }
+
+        progress(PairingStep::Trusting);
+        proxy.set_trusted(true).await?;
+
+        progress(PairingStep::Connecting);
+        proxy.connect().await?;
+
+        progress(PairingStep::Connected);
+        Ok(())
+    }
+
+    async fn device_proxy(&self, address: &str) -> zbus::Result<(Device, Device1Proxy<'static>)> {
+        let device = self.device(address).await?
+            .ok_or_else(|| zbus::Error::Failure(format!("{} is unknown to BlueZ", address)))?;
+        let proxy = Device1Proxy::builder(&self.connection)
+            .path(device.path.clone())?
+            .build()
+            .await?;
+        Ok((device, proxy))
+    }
+
+    /* connects a paired device, nothing happens if it is connected already */
+    pub async fn connect(&self, address: &str) -> zbus::Result<()> {
+        let (device, proxy) = self.device_proxy(address).await?;
+        if !device.connected {
+            proxy.connect().await?;
+        }
+        Ok(())
+    }
+
+    pub async fn disconnect(&self, address: &str) -> zbus::Result<()> {
+        let (device, proxy) = self.device_proxy(address).await?;
+        if device.connected {
+            proxy.disconnect().await?;
+        }
+        Ok(())
+    }
+
+    pub async fn devices(&self) -> zbus::Result<Vec<Device>> {
+        let objects = self.objects().await?;
+
+        Ok(objects.into_iter()
+            .filter_map(|(path, interfaces)| {
+                let device = interfaces.iter()
+                    .find(|(name, _)| name.as_str() == DEVICE_INTERFACE)?.1;
+                let battery = interfaces.iter()
+                    .find(|(name, _)| name.as_str() == BATTERY_INTERFACE)
+                    .map(|(_, properties)| properties);
+                Some(Device {
+                    address: property::<String>(device, "Address")?,
+                    alias: property(device, "Alias"),
+                    paired: property(device, "Paired").unwrap_or(false),
+                    connected: property(device, "Connected").unwrap_or(false),
+                    battery: battery.and_then(|battery| property(battery, "Percentage")),
+                    battery_source: battery.and_then(|battery| property(battery, "Source")),
+                    modalias: property(device, "Modalias"),
+                    path,
+                })
+            })
+            .collect())
+    }
+}
+
+fn property<T>(properties: &HashMap<String, OwnedValue>, name: &str) -> Option<T>
+where
+    T: TryFrom<OwnedValue>,
+{
+    properties.get(name)
+        .and_then(|value| value.try_clone().ok())
+        .and_then(|value| T::try_from(value).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{device_path, MockBlueZ, MockDevice, PrivateBus};
+
+    const ADDRESS: &str = "AA:BB:CC:DD:EE:01";
+
+    #[tokio::test]
+    async fn devices_with_battery() {
+        let bus = PrivateBus::start();
+        let mock = MockBlueZ::start(&bus).await;
+        mock.add_device(MockDevice { paired: true, connected: true, ..MockDevice::new("AA:BB:CC:DD:EE:01", "Work AirPods") }, Some(73)).await;
+        mock.add_device(MockDevice { paired: true, ..MockDevice::new("AA:BB:CC:DD:EE:02", "Old AirPods") }, None).await;
+
+        let bluez = BlueZ::new(bus.connect().await);
+        let mut devices = bluez.devices().await.unwrap();
+        devices.sort_by(|a, b| a.address.cmp(&b.address));
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].path, device_path("AA:BB:CC:DD:EE:01"));
+        assert_eq!(devices[0].name(), "Work AirPods");
+        assert!(devices[0].connected && devices[0].paired);
+        assert_eq!(devices[0].battery, Some(73));
+        assert_eq!(devices[0].model(), Some(Model::AirPodsPro));
+        assert!(!devices[1].connected);
+        assert_eq!(devices[1].battery, None);
+        assert_eq!(bluez.device("aa:bb:cc:dd:ee:02").await.unwrap().map(|device| device.address),
+            Some("AA:BB:CC:DD:EE:02".to_string()));
+    }
+
+    #[tokio::test]
+    async fn pair_and_connect_a_discovered_device() {
+        let bus = PrivateBus::start();
+        let mock = MockBlueZ::start(&bus).await;
+        mock.add_discoverable(MockDevice::new(ADDRESS, "AirPods Pro")).await;
+
+        let mut steps = Vec::new();
+        BlueZ::new(bus.connect().await).pair_and_connect(ADDRESS, |step| steps.push(step)).await.unwrap();
+        assert_eq!(steps, [PairingStep::Discovering, PairingStep::Pairing, PairingStep::Trusting,
+            PairingStep::Connecting, PairingStep::Connected]);
+        assert_eq!(mock.calls.take(), [
+            "StartDiscovery".to_string(),
+            "StopDiscovery".to_string(),
+            format!("RegisterAgent {} NoInputNoOutput", AGENT_PATH),
+            format!("Pair {}", ADDRESS),
+            format!("UnregisterAgent {}", AGENT_PATH),
+            format!("Trust {}", ADDRESS),
+            format!("Connect {}", ADDRESS),
+        ]);
+        let device = mock.device(ADDRESS).await;
+        assert!(device.paired && device.trusted && device.connected);
+    }
+
+    #[tokio::test]
+    async fn concurrent_pairings_take_turns() {
+        let bus = PrivateBus::start();
+        let mock = MockBlueZ::start(&bus).await;
+        // known, but not paired yet
+        mock.add_device(MockDevice::new(ADDRESS, "AirPods Pro"), None).await;
+        mock.add_device(MockDevice::new("AA:BB:CC:DD:EE:02", "AirPods Max"), None).await;
+
+        let bluez = BlueZ::new(bus.connect().await);
+        let other = bluez.clone();
+        let (first, second) = tokio::join!(
+            bluez.pair_and_connect(ADDRESS, |_| ()),
+            other.pair_and_connect("AA:BB:CC:DD:EE:02", |_| ()));
+        first.unwrap();
+        second.unwrap();
+        // the mock refuses a second agent, as BlueZ does
+        let agent: Vec<String> = mock.calls.take().into_iter()
+            .filter(|call| call.starts_with("RegisterAgent") || call.starts_with("UnregisterAgent"))
+            .map(|call| call.split(' ').next().unwrap().to_string())
+            .collect();
+        assert_eq!(agent, ["RegisterAgent", "UnregisterAgent", "RegisterAgent", "UnregisterAgent"]);
+    }
+
+    #[tokio::test]
+    async fn paired_device_is_only_connected() {
+        let bus = PrivateBus::start();
+        let mock = MockBlueZ::start(&bus).await;
+        mock.add_device(MockDevice { paired: true, ..MockDevice::new(ADDRESS, "AirPods Pro") }, None).await;
+
+        let mut steps = Vec::new();
+        BlueZ::new(bus.connect().await).pair_and_connect(ADDRESS, |step| steps.push(step)).await.unwrap();
+        assert_eq!(steps, [PairingStep::Discovering, PairingStep::Trusting, PairingStep::Connecting, PairingStep::Connected]);
+        assert_eq!(mock.calls.take(), [format!("Trust {}", ADDRESS), format!("Connect {}", ADDRESS)]);
+    }
+
+    #[tokio::test]
+    async fn discovery_is_stopped_on_errors() {
+        let bus = PrivateBus::start();
+        let mock = MockBlueZ::start(&bus).await;
+        mock.fail_discovery().await;
+
+        let result = BlueZ::new(bus.connect().await).pair_and_connect(ADDRESS, |_| ()).await;
+        // the lookup failed instead of timing out
+        assert!(matches!(result, Err(zbus::Error::FDO(_))));
+        assert_eq!(mock.calls.take(), ["StartDiscovery", "StopDiscovery"]);
+    }
+
+    #[tokio::test]
+    async fn provided_battery_follows_the_level() {
+        let bus = PrivateBus::start();
+        let mock = MockBlueZ::start(&bus).await;
+        let device = device_path(ADDRESS);
+        let mut provider = BatteryProvider::register(&BlueZ::new(bus.connect().await)).await.unwrap();
+        assert!(mock.provided_batteries().await.is_empty());
+
+        provider.publish(&device, 60).await.unwrap();
+        provider.publish(&device, 50).await.unwrap();
+        assert!(provider.is_published(&device));
+        assert_eq!(mock.provided_batteries().await, [(device.to_string(), 50)]);
+
+        provider.remove(&device).await.unwrap();
+        assert!(!provider.is_published(&device));
+        assert!(mock.provided_batteries().await.is_empty());
+    }
+}
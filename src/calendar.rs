@@ -0,0 +1,637 @@
+use crate::battery::{DeviceBattery, format_duration};
+use crate::config::CalendarConfig;
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Meeting {
+    pub uid: String,
+    pub summary: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl Meeting {
+    pub fn duration(&self) -> Duration {
+        (self.end - self.start).to_std().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeetingWarning {
+    pub meeting: Meeting,
+    pub remaining: Duration,
+}
+
+impl MeetingWarning {
+    pub fn summary(&self) -> String {
+        format!("Charge before \"{}\"", self.meeting.summary)
+    }
+
+    pub fn body(&self) -> String {
+        format!("Meeting starts at {} and takes {}.\nBattery lasts about {}.",
+            self.meeting.start.with_timezone(&Local).format("%H:%M"),
+            format_duration(self.meeting.duration()),
+            format_duration(self.remaining))
+    }
+}
+
+/* Checks the upcoming meetings of the configured calendar files against the
+ * remaining battery of the headset. Each meeting is reported only once. */
+pub struct MeetingCheck {
+    config: CalendarConfig,
+    warned: HashSet<(String, DateTime<Utc>)>,
+}
+
+impl MeetingCheck {
+    pub fn new(config: CalendarConfig) -> MeetingCheck {
+        MeetingCheck {
+            config,
+            warned: HashSet::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.config.files.is_empty()
+    }
+
+    fn upcoming(&self, now: DateTime<Utc>) -> Vec<Meeting> {
+        let lead = chrono::Duration::from_std(self.config.lead()).unwrap_or_default();
+        self.config.files.iter()
+            .flat_map(|path| match read_meetings(path, now, now + lead) {
+                Ok(meetings) => meetings,
+                Err(err) => {
+                    eprintln!("calendar {}: {}", path.display(), err);
+                    Vec::new()
+                }
+            })
+            .filter(|meeting| meeting.start > now)
+            .collect()
+    }
+
+    pub fn check(&mut self, now: DateTime<Utc>, headset: &DeviceBattery) -> Vec<MeetingWarning> {
+        let remaining = match headset.remaining_talk_time() {
+            Some(remaining) => remaining,
+            None => return Vec::new(),
+        };
+        let reserve = self.config.reserve();
+
+        let mut warnings = Vec::new();
+        for meeting in self.upcoming(now) {
+            let key = (meeting.uid.clone(), meeting.start);
+            if self.warned.contains(&key) {
+                continue;
+            }
+            if meeting.duration() + reserve > remaining {
+                self.warned.insert(key);
+                warnings.push(MeetingWarning { meeting, remaining });
+            }
+        }
+        // forget meetings which are over
+        self.warned.retain(|(_, start)| *start > now);
+        warnings
+    }
+}
+
+/* meetings which start within the given time span */
+pub fn read_meetings(path: &Path, from: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<Meeting>, std::io::Error> {
+    Ok(parse_meetings(&fs::read_to_string(path)?, from, until))
+}
+
+/* Minimal iCalendar (RFC 5545) reader. Only timed VEVENTs are considered,
+ * all-day and cancelled events are ignored. Recurring events are expanded
+ * into the meetings which start within the given time span. */
+pub fn parse_meetings(content: &str, from: DateTime<Utc>, until: DateTime<Utc>) -> Vec<Meeting> {
+    let events = parse_events(content);
+    // moved or cancelled occurrences replace those of the rule
+    let overridden: HashSet<(String, DateTime<Utc>)> = events.iter()
+        .filter_map(|event| Some((event.uid.clone(), event.recurrence_id?)))
+        .collect();
+    let mut meetings = Vec::new();
+    for event in events {
+        if event.cancelled {
+            continue;
+        }
+        let duration = event.end - event.start.to_utc();
+        let starts = match &event.rule {
+            Some(rule) if event.recurrence_id.is_none() => rule.occurrences(event.start, until),
+            _ => vec![event.start.to_utc()],
+        };
+        for start in starts {
+            if start < from || start > until || event.exdates.contains(&start)
+                || (event.recurrence_id.is_none() && overridden.contains(&(event.uid.clone(), start))) {
+                continue;
+            }
+            meetings.push(Meeting {
+                uid: event.uid.clone(),
+                summary: event.summary.clone(),
+                start,
+                end: start + duration,
+            });
+        }
+    }
+    meetings
+}
+
+fn parse_events(content: &str) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut depth = 0;
+    let mut event: Option<EventBuilder> = None;
+
+    for line in unfold(content) {
+        let (name, params, value) = match split_property(&line) {
+            Some(property) => property,
+            None => continue,
+        };
+        match (name.as_str(), value) {
+            ("BEGIN", "VEVENT") if event.is_none() => {
+                event = Some(EventBuilder::default());
+                depth = 0;
+            }
+            ("BEGIN", _) if event.is_some() => depth += 1,
+            ("END", "VEVENT") if depth == 0 => {
+                if let Some(event) = event.take().and_then(EventBuilder::build) {
+                    events.push(event);
+                }
+            }
+            ("END", _) if event.is_some() => depth -= 1,
+            (_, _) if depth == 0 => {
+                if let Some(event) = event.as_mut() {
+                    event.property(&name, &params, value);
+                }
+            }
+            _ => (),
+        }
+    }
+    events
+}
+
+/* time zone of a local time, which is kept for the recurrence */
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Zone {
+    Utc,
+    Named(Tz),
+    /* floating time */
+    Local,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Time {
+    local: NaiveDateTime,
+    zone: Zone,
+}
+
+impl Time {
+    fn at(&self, local: NaiveDateTime) -> Time {
+        Time { local, zone: self.zone }
+    }
+
+    /* skipped times of a DST change are moved forward by an hour */
+    fn to_utc(self) -> DateTime<Utc> {
+        let resolve = |local: &NaiveDateTime| match self.zone {
+            Zone::Utc => Some(Utc.from_utc_datetime(local)),
+            Zone::Named(tz) => tz.from_local_datetime(local).earliest().map(|time| time.to_utc()),
+            Zone::Local => Local.from_local_datetime(local).earliest().map(|time| time.to_utc()),
+        };
+        resolve(&self.local)
+            .or_else(|| resolve(&(self.local + chrono::Duration::hours(1))))
+            .unwrap_or_else(|| Utc.from_utc_datetime(&self.local))
+    }
+}
+
+struct Event {
+    uid: String,
+    summary: String,
+    start: Time,
+    end: DateTime<Utc>,
+    cancelled: bool,
+    rule: Option<Rule>,
+    exdates: Vec<DateTime<Utc>>,
+    /* start of the occurrence which this event replaces */
+    recurrence_id: Option<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+struct EventBuilder {
+    uid: Option<String>,
+    summary: Option<String>,
+    start: Option<Time>,
+    end: Option<DateTime<Utc>>,
+    duration: Option<chrono::Duration>,
+    cancelled: bool,
+    all_day: bool,
+    rule: Option<String>,
+    exdates: Vec<DateTime<Utc>>,
+    recurrence_id: Option<DateTime<Utc>>,
+}
+
+impl EventBuilder {
+    fn property(&mut self, name: &str, params: &[(String, String)], value: &str) {
+        let date_only = || params.iter().any(|(key, value)| key == "VALUE" && value == "DATE")
+            || value.len() == 8;
+        match name {
+            "UID" => self.uid = Some(value.to_string()),
+            "SUMMARY" => self.summary = Some(unescape(value)),
+            "STATUS" => self.cancelled = value == "CANCELLED",
+            "DTSTART" if date_only() => self.all_day = true,
+            "DTSTART" => self.start = parse_date_time(params, value),
+            "DTEND" => self.end = parse_date_time(params, value).map(Time::to_utc),
+            "DURATION" => self.duration = parse_duration(value),
+            "RRULE" => self.rule = Some(value.to_string()),
+            "EXDATE" => self.exdates.extend(value.split(',')
+                .filter_map(|value| parse_date_time(params, value))
+                .map(Time::to_utc)),
+            "RECURRENCE-ID" => self.recurrence_id = parse_date_time(params, value).map(Time::to_utc),
+            _ => (),
+        }
+    }
+
+    fn build(self) -> Option<Event> {
+        if self.all_day {
+            return None;
+        }
+        let start = self.start?;
+        let end = self.end.or_else(|| self.duration.map(|duration| start.to_utc() + duration))?;
+        Some(Event {
+            uid: self.uid.unwrap_or_default(),
+            summary: self.summary.unwrap_or_else(|| "Meeting".to_string()),
+            start,
+            end,
+            cancelled: self.cancelled,
+            rule: self.rule.as_deref().and_then(|rule| Rule::parse(rule, start.zone)),
+            exdates: self.exdates,
+            recurrence_id: self.recurrence_id,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/* RRULE with FREQ, INTERVAL, COUNT, UNTIL, BYDAY and a single BYMONTHDAY.
+ * Rules with other parts are not expanded, only their first occurrence counts. */
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    frequency: Frequency,
+    interval: u32,
+    count: Option<u32>,
+    until: Option<DateTime<Utc>>,
+    /* weekday with the optional position in the month, e.g. -1FR */
+    by_day: Vec<(Option<i32>, Weekday)>,
+    by_month_day: Option<u32>,
+}
+
+impl Rule {
+    fn parse(value: &str, zone: Zone) -> Option<Rule> {
+        let mut rule = Rule {
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: None,
+        };
+        let mut frequency = None;
+        for part in value.split(';') {
+            let (key, value) = part.split_once('=')?;
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => frequency = Some(match value {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    _ => return None,
+                }),
+                "INTERVAL" => rule.interval = value.parse().ok().filter(|interval| *interval > 0)?,
+                "COUNT" => rule.count = Some(value.parse().ok()?),
+                // a date includes the whole day
+                "UNTIL" if value.len() == 8 => {
+                    let day = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+                    rule.until = Some(Time { local: day.and_hms_opt(23, 59, 59)?, zone }.to_utc());
+                }
+                "UNTIL" => rule.until = Some(parse_date_time(&[], value)?.to_utc()),
+                "BYDAY" => rule.by_day = value.split(',').map(parse_weekday).collect::<Option<_>>()?,
+                "BYMONTHDAY" => rule.by_month_day = Some(value.parse().ok()?),
+                "WKST" => (),
+                _ => return None,
+            }
+        }
+        rule.frequency = frequency?;
+        Some(rule)
+    }
+
+    /* days of the period which starts at the given day */
+    fn days(&self, period: NaiveDate, first: NaiveDate) -> Vec<NaiveDate> {
+        let weekdays = |days: &mut dyn Iterator<Item = NaiveDate>| -> Vec<NaiveDate> {
+            days.filter(|day| self.by_day.iter().any(|(_, weekday)| *weekday == day.weekday())).collect()
+        };
+        match self.frequency {
+            Frequency::Daily => vec![period],
+            Frequency::Weekly if self.by_day.is_empty() => vec![period + Days::new(u64::from(first.weekday().num_days_from_monday()))],
+            Frequency::Weekly => weekdays(&mut period.iter_days().take(7)),
+            Frequency::Monthly | Frequency::Yearly => {
+                // yearly rules repeat in the month of DTSTART
+                let month = match self.frequency {
+                    Frequency::Yearly => period.with_month(first.month()).unwrap_or(period),
+                    _ => period,
+                };
+                let days: Vec<NaiveDate> = month.iter_days().take_while(|day| day.month() == month.month()).collect();
+                if self.by_day.is_empty() {
+                    let day = self.by_month_day.unwrap_or(first.day());
+                    return days.into_iter().filter(|candidate| candidate.day() == day).collect();
+                }
+                let mut selected = Vec::new();
+                for (position, weekday) in &self.by_day {
+                    let matching: Vec<NaiveDate> = days.iter().copied().filter(|day| day.weekday() == *weekday).collect();
+                    match position {
+                        Some(position) if *position > 0 => selected.extend(matching.get(*position as usize - 1)),
+                        Some(position) => selected.extend(matching.len().checked_sub(position.unsigned_abs() as usize)
+                            .and_then(|index| matching.get(index))),
+                        None => selected.extend(matching),
+                    }
+                }
+                selected.sort();
+                selected
+            }
+        }
+    }
+
+    /* starts of the occurrences up to the given time, DTSTART is the first one */
+    fn occurrences(&self, start: Time, until: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+        let first = start.local.date();
+        let mut period = match self.frequency {
+            Frequency::Daily => first,
+            Frequency::Weekly => first - Days::new(u64::from(first.weekday().num_days_from_monday())),
+            Frequency::Monthly => first.with_day(1).unwrap_or(first),
+            Frequency::Yearly => first.with_day(1).and_then(|day| day.with_month(1)).unwrap_or(first),
+        };
+        let end = self.until.map_or(until, |rule_end| rule_end.min(until));
+        let mut starts = vec![start.to_utc()];
+        let mut count = 1;
+        loop {
+            for day in self.days(period, first) {
+                let time = start.at(day.and_time(start.local.time()));
+                if time.local <= start.local {
+                    continue;
+                }
+                let utc = time.to_utc();
+                if utc > end || self.count.is_some_and(|max| count >= max) {
+                    return starts;
+                }
+                starts.push(utc);
+                count += 1;
+            }
+            period = match self.frequency {
+                Frequency::Daily => period + Days::new(u64::from(self.interval)),
+                Frequency::Weekly => period + Days::new(7 * u64::from(self.interval)),
+                Frequency::Monthly => period + Months::new(self.interval),
+                Frequency::Yearly => period + Months::new(12 * self.interval),
+            };
+            if start.at(period.and_time(start.local.time())).to_utc() > end {
+                return starts;
+            }
+        }
+    }
+}
+
+/* e.g. MO, 2TU or -1FR */
+fn parse_weekday(value: &str) -> Option<(Option<i32>, Weekday)> {
+    let split = value.len().checked_sub(2)?;
+    let weekday = match &value[split..] {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    };
+    let position = match &value[..split] {
+        "" => None,
+        position => Some(position.parse().ok().filter(|position| *position != 0)?),
+    };
+    Some((position, weekday))
+}
+
+/* long lines are folded with a leading white space */
+fn unfold(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.trim_end_matches('\r').to_string()),
+        }
+    }
+    lines
+}
+
+type Params = Vec<(String, String)>;
+
+/* NAME;PARAM=VALUE;PARAM="VALUE":VALUE */
+fn split_property(line: &str) -> Option<(String, Params, &str)> {
+    let mut quoted = false;
+    let colon = line.char_indices().find(|(_, c)| {
+        if *c == '"' {
+            quoted = !quoted;
+        }
+        *c == ':' && !quoted
+    })?.0;
+    let mut head = line[..colon].split(';');
+    let name = head.next()?.to_ascii_uppercase();
+    let params = head
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| (key.to_ascii_uppercase(), value.trim_matches('"').to_string()))
+        .collect();
+    Some((name, params, line[colon + 1..].trim_end_matches('\r')))
+}
+
+fn parse_date_time(params: &[(String, String)], value: &str) -> Option<Time> {
+    if let Some(utc) = value.strip_suffix('Z') {
+        let local = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some(Time { local, zone: Zone::Utc });
+    }
+    let local = match NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        Ok(time) => time,
+        Err(_) => NaiveDate::parse_from_str(value, "%Y%m%d").ok()?.and_hms_opt(0, 0, 0)?,
+    };
+    let zone = match params.iter().find(|(key, _)| key == "TZID") {
+        Some((_, tzid)) => Zone::Named(tzid.parse().ok()?),
+        None => Zone::Local,
+    };
+    Some(Time { local, zone })
+}
+
+/* e.g. PT1H30M, P1D, P1W */
+fn parse_duration(value: &str) -> Option<chrono::Duration> {
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let mut seconds: i64 = 0;
+    let mut number = String::new();
+    let mut time = false;
+    for c in value.strip_prefix('P')?.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => time = true,
+            // months are not supported
+            'M' if !time => return None,
+            unit => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                seconds += n * match unit {
+                    'W' => 7 * 24 * 3600,
+                    'D' => 24 * 3600,
+                    'H' => 3600,
+                    'M' => 60,
+                    'S' => 1,
+                    _ => return None,
+                };
+            }
+        }
+    }
+    Some(chrono::Duration::seconds(if negative { -seconds } else { seconds }))
+}
+
+fn unescape(value: &str) -> String {
+    value.replace("\\n", " ")
+        .replace("\\N", " ")
+        .replace("\\,", ",")
+        .replace("\\;", ";")
+        .replace("\\\\", "\\")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap())
+    }
+
+    fn calendar(events: &str) -> String {
+        format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{}END:VCALENDAR\r\n", events)
+    }
+
+    fn starts(content: &str, from: &str, until: &str) -> Vec<DateTime<Utc>> {
+        let mut starts: Vec<_> = parse_meetings(content, utc(from), utc(until)).into_iter()
+            .map(|meeting| meeting.start)
+            .collect();
+        starts.sort();
+        starts
+    }
+
+    #[test]
+    fn single_event() {
+        let content = calendar("BEGIN:VEVENT\r\nUID:1\r\nSUMMARY:Design review\\, part 2\r\n\
+            DTSTART;TZID=Europe/Berlin:20240610T140000\r\nDTEND;TZID=Europe/Berlin:20240610T153000\r\n\
+            BEGIN:VALARM\r\nTRIGGER:-PT5M\r\nEND:VALARM\r\nEND:VEVENT\r\n");
+        let meetings = parse_meetings(&content, utc("2024-06-10 00:00"), utc("2024-06-11 00:00"));
+        assert_eq!(meetings, vec![Meeting {
+            uid: "1".to_string(),
+            summary: "Design review, part 2".to_string(),
+            start: utc("2024-06-10 12:00"),
+            end: utc("2024-06-10 13:30"),
+        }]);
+        assert!(parse_meetings(&content, utc("2024-06-11 00:00"), utc("2024-06-12 00:00")).is_empty());
+    }
+
+    #[test]
+    fn folded_lines_and_duration() {
+        let content = calendar("BEGIN:VEVENT\r\nUID:2\r\nSUMMARY:Quarterly\r\n  planning\r\n\
+            DTSTART:20240610T080000Z\r\nDURATION:PT1H30M\r\nEND:VEVENT\r\n");
+        let meetings = parse_meetings(&content, utc("2024-06-10 00:00"), utc("2024-06-11 00:00"));
+        assert_eq!(meetings[0].summary, "Quarterly planning");
+        assert_eq!(meetings[0].duration(), Duration::from_secs(90 * 60));
+    }
+
+    #[test]
+    fn all_day_and_cancelled_events_are_ignored() {
+        let content = calendar("BEGIN:VEVENT\r\nUID:3\r\nDTSTART;VALUE=DATE:20240610\r\nEND:VEVENT\r\n\
+            BEGIN:VEVENT\r\nUID:4\r\nSTATUS:CANCELLED\r\nDTSTART:20240610T080000Z\r\nDTEND:20240610T090000Z\r\nEND:VEVENT\r\n");
+        assert!(parse_meetings(&content, utc("2024-06-09 00:00"), utc("2024-06-12 00:00")).is_empty());
+    }
+
+    #[test]
+    fn daily_rule_keeps_the_local_time_across_dst() {
+        let content = calendar("BEGIN:VEVENT\r\nUID:standup\r\nDTSTART;TZID=Europe/Berlin:20240329T093000\r\n\
+            DTEND;TZID=Europe/Berlin:20240329T094500\r\nRRULE:FREQ=DAILY\r\nEND:VEVENT\r\n");
+        // daylight saving time starts on 2024-03-31
+        assert_eq!(starts(&content, "2024-03-30 00:00", "2024-04-01 23:00"), vec![
+            utc("2024-03-30 08:30"),
+            utc("2024-03-31 07:30"),
+            utc("2024-04-01 07:30"),
+        ]);
+    }
+
+    #[test]
+    fn weekly_rule_with_days_count_and_exceptions() {
+        let content = calendar("BEGIN:VEVENT\r\nUID:sync\r\nDTSTART:20240603T100000Z\r\nDTEND:20240603T103000Z\r\n\
+            RRULE:FREQ=WEEKLY;BYDAY=MO,WE,FR;COUNT=5\r\nEXDATE:20240605T100000Z\r\nEND:VEVENT\r\n");
+        assert_eq!(starts(&content, "2024-06-01 00:00", "2024-07-01 00:00"), vec![
+            utc("2024-06-03 10:00"),
+            utc("2024-06-07 10:00"),
+            utc("2024-06-10 10:00"),
+            utc("2024-06-12 10:00"),
+        ]);
+    }
+
+    #[test]
+    fn weekly_rule_with_interval_and_until() {
+        let content = calendar("BEGIN:VEVENT\r\nUID:1on1\r\nDTSTART:20240604T150000Z\r\nDTEND:20240604T153000Z\r\n\
+            RRULE:FREQ=WEEKLY;INTERVAL=2;UNTIL=20240716\r\nEND:VEVENT\r\n");
+        assert_eq!(starts(&content, "2024-06-01 00:00", "2024-08-01 00:00"), vec![
+            utc("2024-06-04 15:00"),
+            utc("2024-06-18 15:00"),
+            utc("2024-07-02 15:00"),
+            utc("2024-07-16 15:00"),
+        ]);
+    }
+
+    #[test]
+    fn monthly_rules() {
+        let last_friday = calendar("BEGIN:VEVENT\r\nUID:retro\r\nDTSTART:20240126T130000Z\r\nDURATION:PT1H\r\n\
+            RRULE:FREQ=MONTHLY;BYDAY=-1FR\r\nEND:VEVENT\r\n");
+        assert_eq!(starts(&last_friday, "2024-02-01 00:00", "2024-04-01 00:00"), vec![
+            utc("2024-02-23 13:00"),
+            utc("2024-03-29 13:00"),
+        ]);
+        // months without the day are skipped
+        let day = calendar("BEGIN:VEVENT\r\nUID:report\r\nDTSTART:20240131T090000Z\r\nDURATION:PT1H\r\n\
+            RRULE:FREQ=MONTHLY\r\nEND:VEVENT\r\n");
+        assert_eq!(starts(&day, "2024-02-01 00:00", "2024-06-01 00:00"), vec![
+            utc("2024-03-31 09:00"),
+            utc("2024-05-31 09:00"),
+        ]);
+    }
+
+    #[test]
+    fn moved_and_cancelled_occurrences() {
+        let content = calendar("BEGIN:VEVENT\r\nUID:standup\r\nDTSTART:20240610T090000Z\r\nDURATION:PT15M\r\n\
+            RRULE:FREQ=DAILY;COUNT=3\r\nEND:VEVENT\r\n\
+            BEGIN:VEVENT\r\nUID:standup\r\nRECURRENCE-ID:20240611T090000Z\r\nDTSTART:20240611T110000Z\r\nDURATION:PT15M\r\nEND:VEVENT\r\n\
+            BEGIN:VEVENT\r\nUID:standup\r\nRECURRENCE-ID:20240612T090000Z\r\nSTATUS:CANCELLED\r\n\
+            DTSTART:20240612T090000Z\r\nDURATION:PT15M\r\nEND:VEVENT\r\n");
+        assert_eq!(starts(&content, "2024-06-01 00:00", "2024-07-01 00:00"), vec![
+            utc("2024-06-10 09:00"),
+            utc("2024-06-11 11:00"),
+        ]);
+    }
+
+    #[test]
+    fn unsupported_rules_keep_the_first_occurrence() {
+        let content = calendar("BEGIN:VEVENT\r\nUID:x\r\nDTSTART:20240610T090000Z\r\nDURATION:PT15M\r\n\
+            RRULE:FREQ=MONTHLY;BYSETPOS=2;BYDAY=MO,TU\r\nEND:VEVENT\r\n");
+        assert_eq!(starts(&content, "2024-06-01 00:00", "2024-09-01 00:00"), vec![utc("2024-06-10 09:00")]);
+    }
+}
@@ -0,0 +1,474 @@
+use crate::proximity::{PairedMessage, Model, Color};
+use crate::audio::AudioProfile;
+use crate::digest::Rule;
+use crate::schedule::Period;
+use crate::template::SymbolSet;
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const APP_DIR: &str = "airpods-notify";
+const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub device: Vec<DeviceConfig>,
+    pub calendar: CalendarConfig,
+    pub left_behind: LeftBehindConfig,
+    pub nearby: NearbyConfig,
+    pub auto_connect: AutoConnectConfig,
+    pub bluez: BlueZConfig,
+    pub ear_detection: EarDetectionConfig,
+    pub audio: AudioConfig,
+    pub lock: LockConfig,
+    pub do_not_disturb: DoNotDisturbConfig,
+    pub quiet_hours: QuietHoursConfig,
+    pub template: TemplateConfig,
+    pub artwork: ArtworkConfig,
+    pub notifier: NotifierConfig,
+    pub tray: TrayConfig,
+}
+
+/* a headset owned by the user, e.g.
+ *
+ * [[device]]
+ * name = "Work AirPods"
+ * model = "AirPodsPro"
+ * color = "White"
+ * address = "AA:BB:CC:DD:EE:FF"
+ * auto_connect = true
+ */
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceConfig {
+    pub name: String,
+    pub model: Model,
+    pub color: Option<Color>,
+    /* public Bluetooth address as known by BlueZ */
+    pub address: Option<String>,
+    /* Identity Resolving Key in the format of /var/lib/bluetooth, which is only readable by root.
+     * Without it the reports are guessed by model, which cannot be muted or hidden. */
+    pub irk: Option<String>,
+    /* connect on lid open, disconnect when the case is closed */
+    #[serde(default)]
+    pub auto_connect: bool,
+}
+
+impl DeviceConfig {
+    pub fn matches(&self, msg: &PairedMessage) -> bool {
+        self.model == msg.model && self.color.is_none_or(|color| color == msg.color)
+    }
+
+    pub fn has_address(&self, address: &str) -> bool {
+        self.address.as_ref().is_some_and(|own| own.eq_ignore_ascii_case(address))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalendarConfig {
+    /* iCalendar files which are checked for upcoming meetings */
+    pub files: Vec<PathBuf>,
+    /* name of the device which is used for meetings, defaults to the first one */
+    pub device: Option<String>,
+    /* warn this many minutes before the meeting starts */
+    pub lead_minutes: u64,
+    /* required battery reserve after the meeting ended */
+    pub reserve_minutes: u64,
+}
+
+impl Default for CalendarConfig {
+    fn default() -> Self {
+        CalendarConfig {
+            files: Vec::new(),
+            device: None,
+            lead_minutes: 15,
+            reserve_minutes: 10,
+        }
+    }
+}
+
+impl CalendarConfig {
+    pub fn lead(&self) -> Duration {
+        Duration::from_secs(self.lead_minutes * 60)
+    }
+
+    pub fn reserve(&self) -> Duration {
+        Duration::from_secs(self.reserve_minutes * 60)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NearbyConfig {
+    /* maximum number of notifications shown at once */
+    pub max_cards: usize,
+    /* urgency by the lowest battery level: critical at or below critical_percent,
+     * normal at or below low_percent and low above */
+    pub critical_percent: u8,
+    pub low_percent: u8,
+    /* updates replace the notification instead of stacking */
+    pub replace: bool,
+}
+
+impl Default for NearbyConfig {
+    fn default() -> Self {
+        NearbyConfig {
+            max_cards: 3,
+            critical_percent: 10,
+            low_percent: 100,
+            replace: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlueZConfig {
+    /* publish the proximity battery level of registered devices to BlueZ */
+    pub battery_provider: bool,
+}
+
+impl Default for BlueZConfig {
+    fn default() -> Self {
+        BlueZConfig { battery_provider: true }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutoConnectConfig {
+    /* minimum time between two automatic (dis)connects of a device */
+    pub cooldown_seconds: u64,
+}
+
+impl Default for AutoConnectConfig {
+    fn default() -> Self {
+        AutoConnectConfig { cooldown_seconds: 30 }
+    }
+}
+
+impl AutoConnectConfig {
+    pub fn cooldown(&self) -> Duration {
+        Duration::from_secs(self.cooldown_seconds)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EarDetectionConfig {
+    pub enabled: bool,
+    /* pause already if one of two worn buds is taken out */
+    pub pause_on_single: bool,
+    /* resume the player paused by the daemon when the buds are put back in */
+    pub resume: bool,
+}
+
+impl Default for EarDetectionConfig {
+    fn default() -> Self {
+        EarDetectionConfig {
+            enabled: true,
+            pause_on_single: true,
+            resume: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    /* use the headset as default sink while both buds are in ear */
+    pub switch_sink: bool,
+    /* card profile of the worn headset */
+    pub profile: Option<AudioProfile>,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            switch_sink: true,
+            profile: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockConfig {
+    pub enabled: bool,
+    /* the headset of the owner, defaults to the first registered device */
+    pub device: Option<String>,
+    /* lock if the headset was not seen for this time */
+    pub absence_seconds: u64,
+    /* lock if the smoothed signal stays below this level for the grace period */
+    pub departure_rssi: i16,
+    pub grace_seconds: u64,
+    /* buds in the closed case, e.g. in a drawer, do not lock the session */
+    pub ignore_in_case: bool,
+}
+
+impl Default for LockConfig {
+    fn default() -> Self {
+        LockConfig {
+            enabled: false,
+            device: None,
+            absence_seconds: 60,
+            departure_rssi: -85,
+            grace_seconds: 15,
+            ignore_in_case: true,
+        }
+    }
+}
+
+impl LockConfig {
+    pub fn absence(&self) -> Duration {
+        Duration::from_secs(self.absence_seconds)
+    }
+
+    pub fn grace(&self) -> Duration {
+        Duration::from_secs(self.grace_seconds)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DoNotDisturbConfig {
+    /* hold notifications back while the user is unavailable */
+    pub enabled: bool,
+    /* critical alerts, e.g. low battery before a meeting, are shown anyway */
+    pub pass_critical: bool,
+}
+
+impl Default for DoNotDisturbConfig {
+    fn default() -> Self {
+        DoNotDisturbConfig {
+            enabled: true,
+            pass_critical: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuietMode {
+    /* held back until the end of the quiet hours */
+    Suppress,
+    /* shown without sound and with low urgency */
+    Downgrade,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuietHoursConfig {
+    pub period: Vec<Period>,
+    pub mode: QuietMode,
+    /* rules which are not affected by the quiet hours */
+    pub except: Vec<Rule>,
+}
+
+impl Default for QuietHoursConfig {
+    fn default() -> Self {
+        QuietHoursConfig {
+            period: Vec::new(),
+            mode: QuietMode::Suppress,
+            except: Vec::new(),
+        }
+    }
+}
+
+impl QuietHoursConfig {
+    pub fn is_quiet(&self, now: &DateTime<Local>) -> bool {
+        self.period.iter().any(|period| period.contains(now))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArtworkConfig {
+    /* images in this directory override the shipped ones,
+     * defaults to $XDG_CONFIG_HOME/airpods-notify/artwork */
+    pub dir: Option<PathBuf>,
+    /* subdirectories of the artwork directories, preferred in this order */
+    pub packs: Vec<String>,
+    /* render battery gauges next to the artwork */
+    pub gauge: bool,
+}
+
+impl Default for ArtworkConfig {
+    fn default() -> Self {
+        ArtworkConfig {
+            dir: None,
+            packs: Vec::new(),
+            gauge: true,
+        }
+    }
+}
+
+impl ArtworkConfig {
+    pub fn dir(&self) -> Option<PathBuf> {
+        self.dir.clone().or_else(|| Config::dir().map(|dir| dir.join("artwork")))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifierBackend {
+    /* the portal inside a sandbox, otherwise the notification server if there is one */
+    Auto,
+    DBus,
+    Portal,
+    Terminal,
+    Log,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifierConfig {
+    pub backend: NotifierBackend,
+    /* escape sequence of the terminal for desktop notifications: 9, 777 or none */
+    pub osc: u16,
+    /* ring the bell of the terminal for critical notifications */
+    pub bell: bool,
+}
+
+impl Default for NotifierConfig {
+    fn default() -> Self {
+        NotifierConfig {
+            backend: NotifierBackend::Auto,
+            osc: 777,
+            bell: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrayConfig {
+    pub enabled: bool,
+    /* the headset shown in the tray, defaults to the first registered device */
+    pub device: Option<String>,
+    /* command which runs the monitor in a terminal window, followed by its arguments */
+    pub terminal: Vec<String>,
+    /* battery icon by the lowest bud: caution at or below the critical_percent of
+     * nearby, low at or below low_percent, full at or above full_percent and good between */
+    pub low_percent: u8,
+    pub full_percent: u8,
+}
+
+impl Default for TrayConfig {
+    fn default() -> Self {
+        TrayConfig {
+            enabled: true,
+            device: None,
+            terminal: vec!["x-terminal-emulator".to_string(), "-e".to_string()],
+            low_percent: 20,
+            full_percent: 80,
+        }
+    }
+}
+
+/* MiniJinja templates of the nearby notifications */
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TemplateConfig {
+    pub symbols: SymbolSet,
+    pub summary: Option<String>,
+    pub body: Option<String>,
+    pub model: Vec<ModelTemplate>,
+}
+
+impl Default for TemplateConfig {
+    fn default() -> Self {
+        TemplateConfig {
+            symbols: SymbolSet::NerdFont,
+            summary: None,
+            body: None,
+            model: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelTemplate {
+    pub model: Model,
+    pub summary: Option<String>,
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LeftBehindConfig {
+    pub enabled: bool,
+    /* alert if only one bud is reported for this time */
+    pub separation_minutes: u64,
+    /* alert if the headset was not seen for this time */
+    pub absence_minutes: u64,
+}
+
+impl Default for LeftBehindConfig {
+    fn default() -> Self {
+        LeftBehindConfig {
+            enabled: true,
+            separation_minutes: 2,
+            absence_minutes: 10,
+        }
+    }
+}
+
+impl LeftBehindConfig {
+    pub fn separation(&self) -> Duration {
+        Duration::from_secs(self.separation_minutes * 60)
+    }
+
+    pub fn absence(&self) -> Duration {
+        Duration::from_secs(self.absence_minutes * 60)
+    }
+}
+
+impl Config {
+    /* $XDG_CONFIG_HOME/airpods-notify */
+    pub fn dir() -> Option<PathBuf> {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join(APP_DIR))
+    }
+
+    /* files written by the daemon itself */
+    pub fn state_dir() -> Option<PathBuf> {
+        env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+            .map(|dir| dir.join(APP_DIR))
+    }
+
+    /* default location is $XDG_CONFIG_HOME/airpods-notify/config.toml */
+    pub fn default_path() -> Option<PathBuf> {
+        Config::dir().map(|dir| dir.join(CONFIG_FILE))
+    }
+
+    pub fn load(path: Option<PathBuf>) -> Result<Config, Box<dyn Error>> {
+        match path {
+            Some(path) => {
+                let content = fs::read_to_string(&path)
+                    .map_err(|err| format!("{}: {}", path.display(), err))?;
+                Ok(toml::from_str(&content)
+                    .map_err(|err| format!("{}: {}", path.display(), err))?)
+            }
+            None => match Config::default_path() {
+                Some(path) if path.exists() => Config::load(Some(path)),
+                _ => Ok(Config::default()),
+            },
+        }
+    }
+
+    pub fn device(&self, name: &str) -> Option<&DeviceConfig> {
+        self.device.iter().find(|device| device.name == name)
+    }
+}
@@ -0,0 +1,103 @@
+use crate::battery::{format_duration, remaining_talk_time, DeviceBattery};
+use crate::nearby::{Card, Seen};
+use chrono::Local;
+use notify_rust::Notification;
+use std::fmt::Write;
+
+pub fn describe(seen: &Seen) -> String {
+    let mut line = format!("{}%", seen.percent);
+    if seen.charging {
+        line.push_str(", charging");
+    }
+    // the current report is not older than the card itself
+    let age = Local::now() - seen.at;
+    if age.num_minutes() >= 1 {
+        write!(line, ", last seen {}", seen.at.format("%H:%M")).unwrap();
+    }
+    line
+}
+
+/* the parts of the device with their last report */
+pub fn components<H>(card: &Card<H>) -> Vec<(&'static str, Option<Seen>)> {
+    if card.msg.model.is_single_device() {
+        vec![("Headphones", card.left)]
+    } else {
+        vec![("Left", card.left), ("Right", card.right), ("Case", card.case)]
+    }
+}
+
+/* Breakdown of a nearby card, shown by its Details button: each component
+ * with its last reported level, the time it was seen and the estimated talk
+ * time of the buds. */
+pub fn details<H>(card: &Card<H>, battery: Option<&DeviceBattery>) -> Notification {
+    let name = card.identity.as_ref().map(|identity| identity.alias.clone())
+        .or_else(|| card.device.clone())
+        .unwrap_or_else(|| card.msg.model.to_string());
+    let mut body = String::new();
+    for (component, seen) in components(card) {
+        match seen {
+            Some(seen) if component == "Case" => writeln!(body, "{}: {}", component, describe(&seen)),
+            Some(seen) => match remaining_talk_time(&card.msg.model, seen.percent) {
+                Some(talk_time) => writeln!(body, "{}: {}, about {} talk time",
+                    component, describe(&seen), format_duration(talk_time)),
+                None => writeln!(body, "{}: {}", component, describe(&seen)),
+            },
+            None => writeln!(body, "{}: not seen", component),
+        }.unwrap();
+    }
+    if let Some(reading) = battery.and_then(|battery| battery.headset()) {
+        writeln!(body, "Headset: {} from {}, {}s ago", reading, reading.source, reading.age().as_secs()).unwrap();
+    }
+    if let Some(identity) = &card.identity {
+        let state = if identity.connected { "connected to this machine" } else { "paired" };
+        writeln!(body, "{} · {}", identity.address, state).unwrap();
+    }
+    write!(body, "{} {}", card.msg.model, card.msg.color).unwrap();
+
+    let mut notification = Notification::new();
+    notification.summary(format!("{} details", name).as_str());
+    notification.body(body.as_str());
+    notification.timeout(notify_rust::Timeout::Default);
+    notification
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::Identity;
+    use crate::proximity::{Battery, PairedMessage};
+    use crate::testing::paired_message;
+    use btleplug::api::BDAddr;
+
+    fn card(msg: PairedMessage, identity: Option<Identity>) -> Card<()> {
+        Card::new(Some("Work".to_string()), identity, BDAddr::from([0x51, 0, 0, 0, 0, 1]), -60, msg, ())
+    }
+
+    #[test]
+    fn details_list_the_parts_and_the_device() {
+        let identity = Identity {
+            alias: "Work AirPods".to_string(),
+            address: "AA:BB:CC:DD:EE:01".to_string(),
+            connected: true,
+            resolved: true,
+        };
+        let msg = PairedMessage { case_battery_level: Battery::None, right_charging: true, ..paired_message(8, 5, 5) };
+        let notification = details(&card(msg, Some(identity)), None);
+        assert_eq!(notification.summary, "Work AirPods details");
+        assert_eq!(notification.body, "Left: 80%, about 2h 48min talk time\n\
+            Right: 50%, charging, about 1h 45min talk time\n\
+            Case: not seen\n\
+            AA:BB:CC:DD:EE:01 · connected to this machine\n\
+            AirPodsPro White");
+    }
+
+    #[test]
+    fn old_reports_show_when_they_were_seen() {
+        let mut card = card(paired_message(8, 5, 5), None);
+        let at = Local::now() - chrono::Duration::minutes(5);
+        card.case = card.case.map(|case| Seen { at, ..case });
+        let notification = details(&card, None);
+        assert_eq!(notification.summary, "Work details");
+        assert!(notification.body.contains(&format!("Case: 50%, last seen {}\n", at.format("%H:%M"))));
+    }
+}
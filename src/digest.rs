@@ -0,0 +1,159 @@
+use crate::availability::Away;
+use crate::config::{DoNotDisturbConfig, QuietHoursConfig, QuietMode};
+use notify_rust::{Hint, Notification, Urgency};
+use serde::Deserialize;
+
+/* origin of a notification, used for the exceptions of the quiet hours */
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    Nearby,
+    Pairing,
+    Meeting,
+    LeftBehind,
+}
+
+/* Holds notifications back while the user is away or during the quiet
+ * hours and merges them into a single digest once the user is back. */
+pub struct Digest {
+    config: DoNotDisturbConfig,
+    quiet_hours: QuietHoursConfig,
+    away: Option<Away>,
+    /* summary and body, the latest one per summary */
+    held: Vec<(String, String)>,
+}
+
+impl Digest {
+    pub fn new(config: DoNotDisturbConfig, quiet_hours: QuietHoursConfig) -> Digest {
+        Digest {
+            config,
+            quiet_hours,
+            away: None,
+            held: Vec::new(),
+        }
+    }
+
+    /* returns the digest when the user is back */
+    pub fn set_away(&mut self, away: Option<Away>) -> Option<Notification> {
+        self.away = away;
+        if self.away.is_some() || self.held.is_empty() {
+            return None;
+        }
+        let body = self.held.drain(..)
+            .map(|(summary, body)| format!("{}: {}", summary, body.lines().next().unwrap_or_default()))
+            .collect::<Vec<String>>()
+            .join("\n");
+        let mut notification = Notification::new();
+        notification.summary("While you were away");
+        notification.body(body.as_str());
+        Some(notification)
+    }
+
+    fn is_quiet(&self, rule: Rule) -> bool {
+        self.away == Some(Away::QuietHours) && !self.quiet_hours.except.contains(&rule)
+    }
+
+    /* true if notifications of the rule are not shown at the moment */
+    pub fn is_holding(&self, rule: Rule, critical: bool) -> bool {
+        match self.away {
+            None => false,
+            Some(Away::QuietHours) => self.is_quiet(rule) && self.quiet_hours.mode == QuietMode::Suppress,
+            Some(_) => !(critical && self.config.pass_critical),
+        }
+    }
+
+    /* false if the notification is held back instead of shown */
+    pub fn admit(&mut self, notification: &mut Notification, rule: Rule) -> bool {
+        let critical = notification.hints.contains(&Hint::Urgency(Urgency::Critical));
+        if self.is_holding(rule, critical) {
+            self.held.retain(|(summary, _)| *summary != notification.summary);
+            self.held.push((notification.summary.clone(), notification.body.clone()));
+            return false;
+        }
+        if self.is_quiet(rule) {
+            notification.hints.retain(|hint| !matches!(hint, Hint::Urgency(_)));
+            notification.urgency(Urgency::Low);
+            notification.hint(Hint::SuppressSound(true));
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(summary: &str, body: &str, urgency: Urgency) -> Notification {
+        let mut notification = Notification::new();
+        notification.summary(summary).body(body).urgency(urgency);
+        notification
+    }
+
+    fn quiet_hours(mode: QuietMode) -> Digest {
+        let quiet_hours = QuietHoursConfig { mode, except: vec![Rule::LeftBehind], ..QuietHoursConfig::default() };
+        let mut digest = Digest::new(DoNotDisturbConfig::default(), quiet_hours);
+        digest.set_away(Some(Away::QuietHours));
+        digest
+    }
+
+    fn digest(pass_critical: bool) -> Digest {
+        Digest::new(DoNotDisturbConfig { enabled: true, pass_critical }, QuietHoursConfig::default())
+    }
+
+    #[test]
+    fn latest_notification_per_summary_is_digested_once() {
+        let mut digest = digest(true);
+        assert_eq!(digest.set_away(Some(Away::Locked)).map(|digest| digest.body), None);
+        assert!(!digest.admit(&mut notification("Work", "Left: 50%\nRight: 50%", Urgency::Low), Rule::Nearby));
+        assert!(!digest.admit(&mut notification("Home", "Left: 80%", Urgency::Low), Rule::Nearby));
+        assert!(!digest.admit(&mut notification("Work", "Left: 40%", Urgency::Low), Rule::Nearby));
+        // still away
+        assert!(digest.set_away(Some(Away::Idle)).is_none());
+
+        let back = digest.set_away(None).unwrap();
+        assert_eq!(back.summary, "While you were away");
+        assert_eq!(back.body, "Home: Left: 80%\nWork: Left: 40%");
+        assert!(digest.set_away(None).is_none());
+        assert!(digest.admit(&mut notification("Work", "Left: 40%", Urgency::Low), Rule::Nearby));
+    }
+
+    #[test]
+    fn critical_passes_only_if_configured() {
+        let mut passing = digest(true);
+        passing.set_away(Some(Away::DoNotDisturb));
+        assert!(!passing.is_holding(Rule::Meeting, true) && passing.is_holding(Rule::Meeting, false));
+        assert!(passing.admit(&mut notification("Meeting", "Low battery", Urgency::Critical), Rule::Meeting));
+        assert!(!passing.admit(&mut notification("Work", "Left: 50%", Urgency::Normal), Rule::Nearby));
+
+        let mut holding = digest(false);
+        holding.set_away(Some(Away::DoNotDisturb));
+        assert!(!holding.admit(&mut notification("Meeting", "Low battery", Urgency::Critical), Rule::Meeting));
+        assert_eq!(holding.set_away(None).unwrap().body, "Meeting: Low battery");
+    }
+
+    #[test]
+    fn quiet_hours_suppress_except_the_rules() {
+        let mut digest = quiet_hours(QuietMode::Suppress);
+        assert!(!digest.admit(&mut notification("Work", "Left: 50%", Urgency::Critical), Rule::Nearby));
+        let mut left_behind = notification("Work left behind", "Last seen at 22:10", Urgency::Normal);
+        assert!(digest.admit(&mut left_behind, Rule::LeftBehind));
+        assert!(left_behind.hints.contains(&Hint::Urgency(Urgency::Normal)));
+        assert_eq!(digest.set_away(None).unwrap().body, "Work: Left: 50%");
+    }
+
+    #[test]
+    fn quiet_hours_downgrade_except_the_rules() {
+        let mut digest = quiet_hours(QuietMode::Downgrade);
+        assert!(!digest.is_holding(Rule::Nearby, false));
+        let mut nearby = notification("Work", "Left: 50%", Urgency::Critical);
+        assert!(digest.admit(&mut nearby, Rule::Nearby));
+        assert!(nearby.hints.contains(&Hint::Urgency(Urgency::Low)));
+        assert!(!nearby.hints.contains(&Hint::Urgency(Urgency::Critical)));
+        assert!(nearby.hints.contains(&Hint::SuppressSound(true)));
+
+        let mut left_behind = notification("Work left behind", "Last seen at 22:10", Urgency::Normal);
+        assert!(digest.admit(&mut left_behind, Rule::LeftBehind));
+        assert!(!left_behind.hints.contains(&Hint::SuppressSound(true)));
+        assert!(digest.set_away(None).is_none());
+    }
+}
@@ -0,0 +1,82 @@
+use crate::config::{DeviceConfig, EarDetectionConfig};
+use crate::proximity::{PairedMessage, Plugged};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaCommand {
+    Pause,
+    Resume,
+}
+
+/* Pauses the media when the worn buds of a registered device are taken
+ * out and resumes it when they are put back in. */
+pub struct EarDetection {
+    config: EarDetectionConfig,
+    devices: HashMap<String, Plugged>,
+}
+
+impl EarDetection {
+    pub fn new(config: EarDetectionConfig) -> EarDetection {
+        EarDetection {
+            config,
+            devices: HashMap::new(),
+        }
+    }
+
+    fn is_worn(&self, plugged: Plugged) -> bool {
+        match plugged {
+            Plugged::Both => true,
+            Plugged::Single => !self.config.pause_on_single,
+            Plugged::None => false,
+        }
+    }
+
+    pub fn update(&mut self, device: &DeviceConfig, msg: &PairedMessage) -> Option<MediaCommand> {
+        if !self.config.enabled {
+            return None;
+        }
+        let previous = self.devices.insert(device.name.clone(), msg.plugged_in_ear)?;
+        match (self.is_worn(previous), self.is_worn(msg.plugged_in_ear)) {
+            (true, false) => Some(MediaCommand::Pause),
+            (false, true) if self.config.resume => Some(MediaCommand::Resume),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::paired_message;
+
+    fn worn(plugged_in_ear: Plugged) -> PairedMessage {
+        PairedMessage { plugged_in_ear, ..paired_message(5, 5, 5) }
+    }
+
+    fn device() -> DeviceConfig {
+        toml::from_str::<crate::config::Config>("[[device]]\nname = \"Work\"\nmodel = \"AirPodsPro\"\n")
+            .unwrap().device.remove(0)
+    }
+
+    #[test]
+    fn taking_out_a_bud_pauses() {
+        let mut ears = EarDetection::new(EarDetectionConfig::default());
+        let device = device();
+        assert_eq!(ears.update(&device, &worn(Plugged::Both)), None);
+        assert_eq!(ears.update(&device, &worn(Plugged::Single)), Some(MediaCommand::Pause));
+        assert_eq!(ears.update(&device, &worn(Plugged::None)), None);
+        assert_eq!(ears.update(&device, &worn(Plugged::Both)), Some(MediaCommand::Resume));
+    }
+
+    #[test]
+    fn single_bud_is_worn_unless_configured() {
+        let config = EarDetectionConfig { pause_on_single: false, resume: false, ..EarDetectionConfig::default() };
+        let mut ears = EarDetection::new(config);
+        let device = device();
+        assert_eq!(ears.update(&device, &worn(Plugged::Both)), None);
+        assert_eq!(ears.update(&device, &worn(Plugged::Single)), None);
+        assert_eq!(ears.update(&device, &worn(Plugged::None)), Some(MediaCommand::Pause));
+        // not resumed without the rule
+        assert_eq!(ears.update(&device, &worn(Plugged::Both)), None);
+    }
+}
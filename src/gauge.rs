@@ -0,0 +1,209 @@
+use crate::proximity::{Battery, PairedMessage, Part, Plugged};
+use image::{imageops, Rgba, RgbaImage};
+use std::f32::consts::PI;
+
+const ARTWORK_SIZE: u32 = 128;
+const WIDTH: u32 = ARTWORK_SIZE + 3 * GAUGE_SPACING;
+const HEIGHT: u32 = ARTWORK_SIZE;
+const GAUGE_SPACING: u32 = 56;
+const RING_RADIUS: f32 = 20.0;
+const RING_THICKNESS: f32 = 6.0;
+const MARKER_RADIUS: f32 = 4.0;
+
+const TRACK: Rgba<u8> = Rgba([128, 128, 128, 96]);
+const LOW: Rgba<u8> = Rgba([230, 60, 50, 255]);
+const MEDIUM: Rgba<u8> = Rgba([240, 180, 30, 255]);
+const HIGH: Rgba<u8> = Rgba([60, 200, 90, 255]);
+
+/* lightning bolt, relative to its center and size */
+const BOLT_SHAPE: [(f32, f32); 6] = [
+    (0.15, -1.0), (-0.55, 0.15), (-0.05, 0.15), (-0.15, 1.0), (0.55, -0.2), (0.05, -0.2),
+];
+
+/* a bud or the case as shown below the artwork */
+struct Gauge {
+    level: u8,
+    charging: bool,
+    /* none for the case */
+    in_ear: Option<bool>,
+}
+
+/* Renders the artwork next to ring gauges of the left and right bud and
+ * of the case, similar to the card of iOS. The rendering only depends on
+ * its input, so equal messages result in equal images. */
+pub fn render(artwork: Option<&RgbaImage>, msg: &PairedMessage) -> RgbaImage {
+    let mut canvas = RgbaImage::new(WIDTH, HEIGHT);
+    if let Some(artwork) = artwork {
+        let artwork = imageops::thumbnail(artwork, ARTWORK_SIZE, ARTWORK_SIZE);
+        let x = (ARTWORK_SIZE - artwork.width()) / 2;
+        let y = (ARTWORK_SIZE - artwork.height()) / 2;
+        imageops::overlay(&mut canvas, &artwork, x.into(), y.into());
+    }
+
+    // the advertising bud is assumed to be the worn one, if only one is in ear
+    let in_ear = |part: Part| match msg.plugged_in_ear {
+        Plugged::Both => true,
+        Plugged::Single => msg.part == part,
+        Plugged::None => false,
+    };
+    let gauges = [
+        gauge(msg.left_battery_level, msg.left_charging, Some(in_ear(Part::LeftEarPlug))),
+        gauge(msg.right_battery_level, msg.right_charging, Some(in_ear(Part::RightEarPlug))),
+        gauge(msg.case_battery_level, msg.case_charging, None),
+    ];
+    // each gauge keeps its slot, even if another one is missing
+    for (index, gauge) in gauges.iter().enumerate().filter_map(|(index, gauge)| Some((index, gauge.as_ref()?))) {
+        let cx = (ARTWORK_SIZE + GAUGE_SPACING / 2 + index as u32 * GAUGE_SPACING) as f32;
+        let cy = HEIGHT as f32 / 2.0 - 8.0;
+        ring(&mut canvas, cx, cy, gauge.level);
+        if gauge.charging {
+            bolt(&mut canvas, cx, cy, RING_RADIUS / 2.0, level_color(gauge.level));
+        }
+        match gauge.in_ear {
+            Some(true) => disc(&mut canvas, cx, cy + RING_RADIUS + 14.0, MARKER_RADIUS, level_color(gauge.level)),
+            Some(false) => disc(&mut canvas, cx, cy + RING_RADIUS + 14.0, MARKER_RADIUS, TRACK),
+            None => (),
+        }
+    }
+    canvas
+}
+
+fn gauge(battery: Battery, charging: bool, in_ear: Option<bool>) -> Option<Gauge> {
+    match battery {
+        Battery::Level(level) => Some(Gauge { level: (level * 10).min(100), charging, in_ear }),
+        Battery::None => None,
+    }
+}
+
+fn level_color(level: u8) -> Rgba<u8> {
+    match level {
+        0..=20 => LOW,
+        21..=50 => MEDIUM,
+        _ => HIGH,
+    }
+}
+
+/* alpha blending of a color with the given coverage of the pixel */
+fn blend(canvas: &mut RgbaImage, x: u32, y: u32, color: Rgba<u8>, coverage: f32) {
+    let alpha = f32::from(color[3]) / 255.0 * coverage.clamp(0.0, 1.0);
+    if alpha <= 0.0 {
+        return;
+    }
+    let pixel = canvas.get_pixel_mut(x, y);
+    let base = f32::from(pixel[3]) / 255.0;
+    let out = alpha + base * (1.0 - alpha);
+    for channel in 0..3 {
+        let value = (f32::from(color[channel]) * alpha + f32::from(pixel[channel]) * base * (1.0 - alpha)) / out;
+        pixel[channel] = value.round() as u8;
+    }
+    pixel[3] = (out * 255.0).round() as u8;
+}
+
+/* calls the function for each pixel center within the radius around the point */
+fn for_each_pixel(canvas: &mut RgbaImage, cx: f32, cy: f32, radius: f32, mut f: impl FnMut(&mut RgbaImage, u32, u32, f32, f32)) {
+    let x0 = (cx - radius - 1.0).max(0.0) as u32;
+    let y0 = (cy - radius - 1.0).max(0.0) as u32;
+    let x1 = ((cx + radius + 1.0) as u32).min(canvas.width() - 1);
+    let y1 = ((cy + radius + 1.0) as u32).min(canvas.height() - 1);
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            f(canvas, x, y, x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+        }
+    }
+}
+
+/* the filled arc starts at the top and runs clockwise */
+fn ring(canvas: &mut RgbaImage, cx: f32, cy: f32, level: u8) {
+    let end = f32::from(level) / 100.0 * 2.0 * PI;
+    let color = level_color(level);
+    for_each_pixel(canvas, cx, cy, RING_RADIUS + RING_THICKNESS, |canvas, x, y, dx, dy| {
+        let distance = (dx * dx + dy * dy).sqrt();
+        let coverage = RING_THICKNESS / 2.0 + 0.5 - (distance - RING_RADIUS).abs();
+        let angle = dx.atan2(-dy).rem_euclid(2.0 * PI);
+        blend(canvas, x, y, if angle <= end { color } else { TRACK }, coverage);
+    });
+}
+
+fn disc(canvas: &mut RgbaImage, cx: f32, cy: f32, radius: f32, color: Rgba<u8>) {
+    for_each_pixel(canvas, cx, cy, radius, |canvas, x, y, dx, dy| {
+        blend(canvas, x, y, color, radius + 0.5 - (dx * dx + dy * dy).sqrt());
+    });
+}
+
+/* even-odd rule with 4x4 samples per pixel */
+fn bolt(canvas: &mut RgbaImage, cx: f32, cy: f32, size: f32, color: Rgba<u8>) {
+    let points = BOLT_SHAPE.map(|(x, y)| (x * size, y * size));
+    let inside = |px: f32, py: f32| {
+        let mut inside = false;
+        for (i, &(x0, y0)) in points.iter().enumerate() {
+            let (x1, y1) = points[(i + 1) % points.len()];
+            if (y0 > py) != (y1 > py) && px < x0 + (py - y0) * (x1 - x0) / (y1 - y0) {
+                inside = !inside;
+            }
+        }
+        inside
+    };
+    for_each_pixel(canvas, cx, cy, size, |canvas, x, y, dx, dy| {
+        let samples = (0..16)
+            .filter(|i| inside(dx - 0.5 + (i % 4) as f32 / 4.0 + 0.125, dy - 0.5 + (i / 4) as f32 / 4.0 + 0.125))
+            .count();
+        blend(canvas, x, y, color, samples as f32 / 16.0);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proximity::Lid;
+    use crate::testing::paired_message;
+    use std::env;
+    use std::path::PathBuf;
+
+    /* compares with res/golden/<name>.png, which is rewritten with UPDATE_GOLDEN=1 */
+    fn assert_golden(name: &str, image: &RgbaImage) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("res/golden").join(format!("{}.png", name));
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            image.save(&path).unwrap();
+        }
+        let golden = image::open(&path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err)).to_rgba8();
+        assert!(golden == *image, "{} differs from {}", name, path.display());
+    }
+
+    fn artwork() -> RgbaImage {
+        image::load_from_memory(include_bytes!("../res/airpodspro.png")).unwrap().to_rgba8()
+    }
+
+    #[test]
+    fn worn_buds_and_charging_case() {
+        let msg = PairedMessage { case_charging: true, ..paired_message(8, 3, 1) };
+        assert_golden("gauge-worn", &render(Some(&artwork()), &msg));
+    }
+
+    #[test]
+    fn missing_bud_keeps_the_slots() {
+        let msg = PairedMessage {
+            left_battery_level: Battery::None,
+            plugged_in_ear: Plugged::Single,
+            part: Part::RightEarPlug,
+            ..paired_message(0, 6, 10)
+        };
+        let image = render(None, &msg);
+        assert_golden("gauge-missing-left", &image);
+        // nothing is drawn in the slot of the left bud
+        let left = ARTWORK_SIZE..ARTWORK_SIZE + GAUGE_SPACING;
+        assert!(image.enumerate_pixels().filter(|(x, _, _)| left.contains(x)).all(|(_, _, pixel)| pixel[3] == 0));
+    }
+
+    #[test]
+    fn charging_buds_in_the_case() {
+        let msg = PairedMessage {
+            lid: Lid::Closed(1),
+            left_charging: true,
+            right_charging: true,
+            plugged_in_ear: Plugged::None,
+            plugged_in_case: Plugged::Both,
+            ..paired_message(10, 2, 5)
+        };
+        assert_golden("gauge-in-case", &render(None, &msg));
+    }
+}
@@ -0,0 +1,97 @@
+use crate::config::NearbyConfig;
+use crate::proximity::{Battery, PairedMessage};
+use notify_rust::{Hint, Notification, Urgency};
+
+/* lowest level of the buds, or of the case if no bud is reported */
+fn lowest_level(msg: &PairedMessage) -> Option<u8> {
+    let level = |battery| match battery {
+        Battery::Level(level) => Some((level * 10).min(100)),
+        Battery::None => None,
+    };
+    level(msg.left_battery_level).into_iter()
+        .chain(level(msg.right_battery_level))
+        .min()
+        .or(level(msg.case_battery_level))
+}
+
+/* Progress bar, urgency, category and stacking hints of a nearby card.
+ * The tag identifies the device, so updates replace its notification. */
+pub fn battery_hints(notification: &mut Notification, msg: &PairedMessage, config: &NearbyConfig, tag: &str) {
+    // an update must not keep the hints of the previous message
+    notification.hints.retain(|hint| !matches!(hint,
+        Hint::Urgency(_) | Hint::Category(_) | Hint::CustomInt(..) | Hint::Custom(..)));
+    notification.hint(Hint::Category("device".to_string()));
+    // Notification::hint keeps custom hints in a private map, which the
+    // notifiers cannot read, so they are added to the hints directly
+    if let Some(level) = lowest_level(msg) {
+        notification.hints.insert(Hint::CustomInt("value".to_string(), i32::from(level)));
+        notification.urgency(match level {
+            level if level <= config.critical_percent => Urgency::Critical,
+            level if level <= config.low_percent => Urgency::Normal,
+            _ => Urgency::Low,
+        });
+    }
+    if config.replace {
+        notification.hints.insert(Hint::Custom("x-dunst-stack-tag".to_string(), tag.to_string()));
+        notification.hints.insert(Hint::Custom("x-canonical-private-synchronous".to_string(), tag.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::paired_message;
+
+    fn custom(notification: &Notification, key: &str) -> Option<String> {
+        notification.hints.iter().find_map(|hint| match hint {
+            Hint::Custom(name, value) if name == key => Some(value.clone()),
+            Hint::CustomInt(name, value) if name == key => Some(value.to_string()),
+            _ => None,
+        })
+    }
+
+    fn urgency(notification: &Notification) -> Option<Urgency> {
+        notification.hints.iter().find_map(|hint| match hint {
+            Hint::Urgency(urgency) => Some(*urgency),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn progress_is_the_lowest_bud() {
+        let mut notification = Notification::new();
+        battery_hints(&mut notification, &paired_message(8, 3, 1), &NearbyConfig::default(), "Work");
+        assert_eq!(custom(&notification, "value").as_deref(), Some("30"));
+
+        // only the case of a stored headset
+        let stored = PairedMessage { left_battery_level: Battery::None, right_battery_level: Battery::None, ..paired_message(8, 3, 6) };
+        battery_hints(&mut notification, &stored, &NearbyConfig::default(), "Work");
+        assert_eq!(custom(&notification, "value").as_deref(), Some("60"));
+        assert_eq!(notification.hints.iter().filter(|hint| matches!(hint, Hint::CustomInt(..))).count(), 1);
+    }
+
+    #[test]
+    fn stack_tag_follows_the_device() {
+        let mut notification = Notification::new();
+        battery_hints(&mut notification, &paired_message(5, 5, 5), &NearbyConfig::default(), "Work");
+        battery_hints(&mut notification, &paired_message(5, 5, 5), &NearbyConfig::default(), "Home");
+        assert_eq!(custom(&notification, "x-dunst-stack-tag").as_deref(), Some("Home"));
+        assert_eq!(custom(&notification, "x-canonical-private-synchronous").as_deref(), Some("Home"));
+        assert_eq!(notification.hints.iter().filter(|hint| matches!(hint, Hint::Custom(..))).count(), 2);
+
+        // stacked notifications carry no tag
+        let stacked = NearbyConfig { replace: false, ..NearbyConfig::default() };
+        battery_hints(&mut notification, &paired_message(5, 5, 5), &stacked, "Work");
+        assert_eq!(custom(&notification, "x-dunst-stack-tag"), None);
+    }
+
+    #[test]
+    fn urgency_follows_the_thresholds() {
+        let config = NearbyConfig { critical_percent: 10, low_percent: 30, ..NearbyConfig::default() };
+        let mut notification = Notification::new();
+        for (level, expected) in [(1, Urgency::Critical), (2, Urgency::Normal), (3, Urgency::Normal), (4, Urgency::Low)] {
+            battery_hints(&mut notification, &paired_message(level, 9, 9), &config, "Work");
+            assert_eq!(urgency(&notification), Some(expected), "{}0%", level);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use crate::bluez::Device;
+use crate::config::{Config, DeviceConfig};
+use crate::proximity::PairedMessage;
+use aes::Aes128;
+use aes::cipher::{Array, BlockCipherEncrypt, KeyInit};
+use btleplug::api::BDAddr;
+use std::collections::HashMap;
+use std::fs;
+
+const BLUEZ_STORAGE: &str = "/var/lib/bluetooth";
+
+/* Identity Resolving Key, which is exchanged while bonding. It resolves
+ * the rotating private addresses used in the advertisements. */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Irk([u8; 16]);
+
+impl Irk {
+    /* BlueZ stores the key as hex string with the least significant byte first */
+    pub fn from_hex(hex: &str) -> Option<Irk> {
+        let hex = hex.trim();
+        if hex.len() != 32 {
+            return None;
+        }
+        let mut key = [0u8; 16];
+        for (i, byte) in key.iter_mut().rev().enumerate() {
+            *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+        }
+        Some(Irk(key))
+    }
+
+    /* configured key of a registered device or the one stored by BlueZ */
+    pub fn for_device(device: &DeviceConfig) -> Option<Irk> {
+        device.irk.as_deref()
+            .and_then(Irk::from_hex)
+            .or_else(|| Irk::load(device.address.as_deref()?))
+    }
+
+    /* [IdentityResolvingKey] of /var/lib/bluetooth/<adapter>/<device>/info */
+    fn load(address: &str) -> Option<Irk> {
+        fs::read_dir(BLUEZ_STORAGE).ok()?
+            .flatten()
+            .filter_map(|adapter| fs::read_to_string(adapter.path().join(address).join("info")).ok())
+            .find_map(|info| {
+                let section = info.split("[IdentityResolvingKey]").nth(1)?;
+                section.lines()
+                    .take_while(|line| !line.starts_with('['))
+                    .find_map(|line| line.strip_prefix("Key="))
+                    .and_then(Irk::from_hex)
+            })
+    }
+
+    /* random part and hash of the address have to match: hash = ah(irk, prand) */
+    pub fn resolves(&self, address: &BDAddr) -> bool {
+        let address = address.into_inner();
+        // the two most significant bits mark a resolvable private address
+        if address[0] >> 6 != 0b01 {
+            return false;
+        }
+        let mut block = Array::from([0u8; 16]);
+        block[13..].copy_from_slice(&address[..3]);
+        Aes128::new(&Array::from(self.0)).encrypt_block(&mut block);
+        block[13..] == address[3..]
+    }
+
+    /* resolvable private address with the given random part */
+    #[cfg(test)]
+    pub fn private_address(&self, prand: [u8; 3]) -> BDAddr {
+        let mut address = [0u8; 6];
+        address[..3].copy_from_slice(&prand);
+        address[0] = address[0] & 0x3f | 0x40;
+        let mut block = Array::from([0u8; 16]);
+        block[13..].copy_from_slice(&address[..3]);
+        Aes128::new(&Array::from(self.0)).encrypt_block(&mut block);
+        address[3..].copy_from_slice(&block[13..]);
+        BDAddr::from(address)
+    }
+}
+
+/* BlueZ device which was matched to a proximity report */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    pub alias: String,
+    pub address: String,
+    pub connected: bool,
+    /* resolved by the IRK, otherwise only guessed by model and bond state */
+    pub resolved: bool,
+}
+
+impl Identity {
+    fn from(device: &Device, resolved: bool) -> Identity {
+        Identity {
+            alias: device.name().to_string(),
+            address: device.address.clone(),
+            connected: device.connected,
+            resolved,
+        }
+    }
+}
+
+/* Paired or connected BlueZ devices, which are matched to proximity
+ * reports by their IRK or otherwise by model and bond state. */
+#[derive(Default)]
+pub struct Identities {
+    devices: Vec<(Device, Option<Irk>)>,
+    /* the storage is read once per device */
+    irks: HashMap<String, Option<Irk>>,
+}
+
+impl Identities {
+    pub fn update(&mut self, devices: &[Device], config: &Config) {
+        self.devices = devices.iter()
+            .filter(|device| device.paired || device.connected)
+            .map(|device| {
+                let irk = *self.irks.entry(device.address.clone()).or_insert_with(|| {
+                    match config.device.iter().find(|registered| registered.has_address(&device.address)) {
+                        Some(registered) => Irk::for_device(registered),
+                        None => Irk::load(&device.address),
+                    }
+                });
+                (device.clone(), irk)
+            })
+            .collect();
+    }
+
+    pub fn resolve(&self, address: &BDAddr, msg: &PairedMessage) -> Option<Identity> {
+        // some devices advertise with their public address
+        let public = address.to_string();
+        if let Some((device, _)) = self.devices.iter()
+            .find(|(device, irk)| device.address.eq_ignore_ascii_case(&public) || irk.is_some_and(|irk| irk.resolves(address))) {
+            return Some(Identity::from(device, true));
+        }
+        // devices with a known key did not send this report
+        let candidates: Vec<&Device> = self.devices.iter()
+            .filter(|(device, irk)| irk.is_none() && device.paired && device.model() == Some(msg.model))
+            .map(|(device, _)| device)
+            .collect();
+        let connected: Vec<&Device> = candidates.iter()
+            .copied()
+            .filter(|device| device.connected)
+            .collect();
+        // ambiguous without a key, if several devices of the model are bonded
+        match (candidates.as_slice(), connected.as_slice()) {
+            ([device], _) | (_, [device]) => Some(Identity::from(device, false)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{device_path, paired_message};
+
+    const IRK: &str = "00112233445566778899aabbccddeeff";
+
+    fn device(address: &str, connected: bool) -> Device {
+        Device {
+            path: device_path(address),
+            address: address.to_string(),
+            alias: Some(format!("AirPods {}", &address[15..])),
+            paired: true,
+            connected,
+            battery: None,
+            battery_source: None,
+            modalias: Some("bluetooth:v004Cp200Ed0A10".to_string()),
+        }
+    }
+
+    fn identities(devices: Vec<(Device, Option<Irk>)>) -> Identities {
+        Identities { devices, irks: HashMap::new() }
+    }
+
+    /* sample data of ah in the Bluetooth Core Specification, Vol 3, Part H,
+     * Appendix D: IRK 0xec0234a357c8ad05341010a60a397d9b, prand 0x708194 and
+     * hash 0x0dfbaa. BlueZ stores the key least significant byte first, while
+     * the address is written most significant byte first. */
+    #[test]
+    fn ah_matches_the_specification() {
+        let irk = Irk::from_hex("9b7d390aa610103405adc857a33402ec").unwrap();
+        let address = BDAddr::from([0x70, 0x81, 0x94, 0x0d, 0xfb, 0xaa]);
+        assert!(irk.resolves(&address));
+        assert_eq!(irk.private_address([0x70, 0x81, 0x94]), address);
+        assert!(!irk.resolves(&BDAddr::from([0x70, 0x81, 0x94, 0x0d, 0xfb, 0xab])));
+    }
+
+    #[test]
+    fn public_address_and_irk_resolve() {
+        let irk = Irk::from_hex(IRK).unwrap();
+        let identities = identities(vec![
+            (device("AA:BB:CC:DD:EE:01", false), None),
+            (device("AA:BB:CC:DD:EE:02", false), Some(irk)),
+        ]);
+        let msg = paired_message(5, 5, 5);
+        let public = identities.resolve(&BDAddr::from([0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0x01]), &msg).unwrap();
+        assert_eq!((public.address.as_str(), public.resolved), ("AA:BB:CC:DD:EE:01", true));
+        let private = identities.resolve(&irk.private_address([1, 2, 3]), &msg).unwrap();
+        assert_eq!((private.address.as_str(), private.resolved), ("AA:BB:CC:DD:EE:02", true));
+    }
+
+    #[test]
+    fn without_key_the_only_bonded_device_of_the_model_is_guessed() {
+        let address = BDAddr::from([0x51, 0x02, 0x03, 0x04, 0x05, 0x06]);
+        let msg = paired_message(5, 5, 5);
+        let single = identities(vec![(device("AA:BB:CC:DD:EE:01", false), None)]);
+        let guessed = single.resolve(&address, &msg).unwrap();
+        assert_eq!((guessed.address.as_str(), guessed.resolved), ("AA:BB:CC:DD:EE:01", false));
+
+        // the connected one of several
+        let several = identities(vec![
+            (device("AA:BB:CC:DD:EE:01", false), None),
+            (device("AA:BB:CC:DD:EE:02", true), None),
+        ]);
+        assert_eq!(several.resolve(&address, &msg).map(|identity| identity.address), Some("AA:BB:CC:DD:EE:02".to_string()));
+
+        // a device with a key did not send a report it does not resolve
+        let keyed = identities(vec![(device("AA:BB:CC:DD:EE:01", false), Irk::from_hex(IRK))]);
+        assert_eq!(keyed.resolve(&address, &msg), None);
+    }
+}
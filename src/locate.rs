@@ -0,0 +1,136 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/* weight of a new sample in the exponential moving average */
+const SMOOTHING: f64 = 0.25;
+/* RSSI range which is mapped onto the gauge */
+const RSSI_FAR: f64 = -100.0;
+const RSSI_NEAR: f64 = -35.0;
+const GAUGE_WIDTH: usize = 40;
+/* the trend compares the current value with the one of this time ago */
+const TREND_WINDOW: Duration = Duration::from_secs(3);
+const TREND_THRESHOLD: f64 = 2.0;
+const BEEP_SLOWEST: Duration = Duration::from_millis(2000);
+const BEEP_FASTEST: Duration = Duration::from_millis(150);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trend {
+    Warmer,
+    Colder,
+    Steady,
+}
+
+impl fmt::Display for Trend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Trend::Warmer => write!(f, "↑ warmer"),
+            Trend::Colder => write!(f, "↓ colder"),
+            Trend::Steady => write!(f, "→ steady"),
+        }
+    }
+}
+
+/* Smooths the RSSI of a single bud to guide the user towards it. The buds
+ * report their own signal, so each one needs its own locator. */
+#[derive(Default)]
+pub struct Locator {
+    smoothed: Option<f64>,
+    history: VecDeque<(Instant, f64)>,
+}
+
+impl Locator {
+    pub fn update(&mut self, rssi: i16) -> f64 {
+        self.update_at(rssi, Instant::now())
+    }
+
+    fn update_at(&mut self, rssi: i16, now: Instant) -> f64 {
+        let rssi = f64::from(rssi);
+        let smoothed = match self.smoothed {
+            Some(previous) => previous + SMOOTHING * (rssi - previous),
+            None => rssi,
+        };
+        self.smoothed = Some(smoothed);
+        self.history.push_back((now, smoothed));
+        // keep a single sample older than the trend window as reference
+        while self.history.len() > 2 && now.duration_since(self.history[1].0) >= TREND_WINDOW {
+            self.history.pop_front();
+        }
+        smoothed
+    }
+
+    pub fn trend(&self) -> Trend {
+        match (self.history.front(), self.history.back()) {
+            (Some((_, old)), Some((_, new))) if new - old >= TREND_THRESHOLD => Trend::Warmer,
+            (Some((_, old)), Some((_, new))) if old - new >= TREND_THRESHOLD => Trend::Colder,
+            _ => Trend::Steady,
+        }
+    }
+
+    /* 0.0 far away, 1.0 right next to the device */
+    pub fn proximity(&self) -> f64 {
+        self.smoothed
+            .map(|rssi| ((rssi - RSSI_FAR) / (RSSI_NEAR - RSSI_FAR)).clamp(0.0, 1.0))
+            .unwrap_or(0.0)
+    }
+
+    pub fn beep_interval(&self) -> Duration {
+        BEEP_SLOWEST.mul_f64(1.0 - self.proximity()) + BEEP_FASTEST.mul_f64(self.proximity())
+    }
+
+    pub fn gauge(&self) -> String {
+        let filled = (self.proximity() * GAUGE_WIDTH as f64).round() as usize;
+        format!("[{}{}]", "#".repeat(filled), " ".repeat(GAUGE_WIDTH - filled))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn rssi_is_smoothed() {
+        let mut locator = Locator::default();
+        let start = Instant::now();
+        assert_eq!(locator.update_at(-80, start), -80.0);
+        assert_eq!(locator.update_at(-40, start + SECOND), -70.0);
+        assert_eq!(locator.update_at(-70, start + 2 * SECOND), -70.0);
+    }
+
+    #[test]
+    fn trend_compares_with_the_window_before() {
+        let mut locator = Locator::default();
+        let start = Instant::now();
+        locator.update_at(-80, start);
+        assert_eq!(locator.trend(), Trend::Steady);
+        locator.update_at(-40, start + SECOND);
+        assert_eq!(locator.trend(), Trend::Warmer);
+        // the rise leaves the window
+        for second in 2..10 {
+            locator.update_at(-70, start + second * SECOND);
+        }
+        assert_eq!(locator.trend(), Trend::Steady);
+        locator.update_at(-100, start + 10 * SECOND);
+        assert_eq!(locator.trend(), Trend::Colder);
+    }
+
+    #[test]
+    fn beeps_get_faster_when_closer() {
+        let mut locator = Locator::default();
+        assert_eq!(locator.beep_interval(), BEEP_SLOWEST);
+        locator.update(-100);
+        assert_eq!(locator.beep_interval(), BEEP_SLOWEST);
+        assert_eq!(locator.gauge(), format!("[{}]", " ".repeat(GAUGE_WIDTH)));
+
+        let mut near = Locator::default();
+        near.update(-30);
+        assert_eq!(near.beep_interval(), BEEP_FASTEST);
+        assert_eq!(near.gauge(), format!("[{}]", "#".repeat(GAUGE_WIDTH)));
+
+        let mut between = Locator::default();
+        between.update(-70);
+        assert!(between.beep_interval() < BEEP_SLOWEST && between.beep_interval() > BEEP_FASTEST);
+    }
+}
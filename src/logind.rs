@@ -0,0 +1,80 @@
+use zbus::{proxy, Connection};
+
+#[proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    /* true if all sessions of the seat are idle */
+    #[zbus(property)]
+    fn idle_hint(&self) -> zbus::Result<bool>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1/session/auto"
+)]
+trait Session {
+    fn lock(&self) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn locked_hint(&self) -> zbus::Result<bool>;
+}
+
+/* Client for systemd-logind. The connection is injected, so the client works
+ * the same way with the system bus or a private bus. */
+#[derive(Clone)]
+pub struct Logind {
+    manager: ManagerProxy<'static>,
+    /* the session of the daemon */
+    session: SessionProxy<'static>,
+}
+
+impl Logind {
+    pub async fn new(connection: &Connection) -> zbus::Result<Logind> {
+        Ok(Logind {
+            manager: ManagerProxy::new(connection).await?,
+            session: SessionProxy::new(connection).await?,
+        })
+    }
+
+    pub async fn system() -> zbus::Result<Logind> {
+        Logind::new(&Connection::system().await?).await
+    }
+
+    pub async fn is_idle(&self) -> zbus::Result<bool> {
+        self.manager.idle_hint().await
+    }
+
+    pub async fn is_locked(&self) -> zbus::Result<bool> {
+        self.session.locked_hint().await
+    }
+
+    pub async fn lock(&self) -> zbus::Result<()> {
+        self.session.lock().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FakeLogind, PrivateBus};
+
+    #[tokio::test]
+    async fn idle_hint_and_lock() {
+        let bus = PrivateBus::start();
+        let fake = FakeLogind::start(&bus).await;
+        let logind = Logind::new(&bus.connect().await).await.unwrap();
+        assert!(!logind.is_idle().await.unwrap());
+        fake.set_idle(true).await;
+        // the fake does not signal the change to the cached property
+        let logind = Logind::new(&bus.connect().await).await.unwrap();
+        assert!(logind.is_idle().await.unwrap());
+
+        assert!(!logind.is_locked().await.unwrap());
+        logind.lock().await.unwrap();
+        assert_eq!(fake.calls.take(), ["Lock"]);
+    }
+}
@@ -1,8 +1,66 @@
 mod proximity;
+mod config;
+mod battery;
+mod calendar;
+mod bluez;
+mod logind;
+mod tracker;
+mod locate;
+mod nearby;
+mod actions;
+mod autoconnect;
+mod identity;
+mod ears;
+mod mpris;
+mod audio;
+mod presence;
+mod availability;
+mod digest;
+mod schedule;
+mod template;
+mod artwork;
+mod gauge;
+mod server;
+mod hints;
+mod registry;
+mod details;
+mod notifier;
+mod portal;
+mod tray;
+#[cfg(test)]
+mod testing;
 
-use crate::proximity::{ProximityEvent, PairedMessage, Color, Model, Lid, Battery};
+use crate::proximity::{ProximityEvent, Lid, Part};
+use crate::config::{Config, DeviceConfig};
+use crate::calendar::MeetingCheck;
+use crate::battery::{BatteryModel, DeviceBattery};
+use crate::bluez::{BlueZ, BatteryProvider, PairingStep, PROVIDER_SOURCE};
+use crate::logind::Logind;
+use crate::tracker::Tracker;
+use crate::locate::Locator;
+use crate::nearby::{device_key, device_tag, Card, Cards};
+use crate::actions::{card_actions, CardAction, Prompts, UserAction};
+use crate::autoconnect::{AutoConnect, Command};
+use crate::identity::{Identities, Identity, Irk};
+use crate::ears::{EarDetection, MediaCommand};
+use crate::mpris::Mpris;
+use crate::audio::{AudioBackend, AudioSwitch, Pactl};
+use crate::presence::PresenceLock;
+use crate::availability::{Availability, Away};
+use crate::digest::{Digest, Rule};
+use crate::template::{Subject, Templates};
+use crate::artwork::Artwork;
+use crate::hints::battery_hints;
+use crate::registry::Registry;
+use crate::details::details;
+use crate::notifier::{Id, Notifier, Response};
+use crate::tray::{Levels, Tray, TrayAction};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
 use std::env;
-use btleplug::api::{Central, CentralEvent, Manager as _, ScanFilter, Peripheral};
+use std::path::PathBuf;
+use std::time::Duration;
+use btleplug::api::{BDAddr, Central, CentralEvent, Manager as _, ScanFilter, Peripheral};
 use btleplug::platform::{Adapter, Manager};
 use std::error::Error;
 use tokio::sync::mpsc;
@@ -14,144 +72,642 @@ use futures::{Stream, StreamExt};
 pub enum Mode {
     Monitor,
     Nearby,
+    Locate,
+}
+
+/* proximity event together with the advertising peripheral */
+pub struct Advertisement {
+    pub address: BDAddr,
+    pub rssi: i16,
+    pub event: ProximityEvent,
 }
 
 const MIN_RSSI: i16 = -60;
+const TICK: Duration = Duration::from_secs(60);
+const BLUEZ_POLL: Duration = Duration::from_secs(30);
+const LOCK_POLL: Duration = Duration::from_secs(5);
+const AVAILABILITY_POLL: Duration = Duration::from_secs(10);
+
+/* shows the notification, unless it is held back for the digest */
+fn show(mut notification: notify_rust::Notification, rule: Rule, digest: &mut Digest, notifier: &mut dyn Notifier) {
+    if digest.admit(&mut notification, rule) {
+        notifier.show(&notification);
+    }
+}
+
+fn show_meeting_warnings(meeting_check: &mut MeetingCheck, headset: Option<&DeviceBattery>, digest: &mut Digest, notifier: &mut dyn Notifier) {
+    if let Some(headset) = headset {
+        for warning in meeting_check.check(chrono::Utc::now(), headset) {
+            let mut notification = notify_rust::Notification::new();
+            notification.summary(warning.summary().as_str());
+            notification.body(warning.body().as_str());
+            notification.urgency(notify_rust::Urgency::Critical);
+            show(notification, Rule::Meeting, digest, notifier);
+        }
+    }
+}
 
-fn image_from(msg: &PairedMessage) -> Option<&str> {
-    match (&msg.model, &msg.color) {
-        (Model::AirPodsPro, _) => Some("airpodspro.png"),
-        (Model::AirPodsPro2, _) => Some("airpodspro.png"),
-        (Model::AirPods1, _) => Some("airpods.png"),
-        (Model::AirPods2, _) => Some("airpods.png"),
-        (Model::AirPods3, _) => Some("airpodspro.png"),
-        (Model::BeatsFlex, _) => Some("beatsflex-black.png"), // black, yellow, blue, gray
-        (Model::BeatsSolo3, _) => Some("beatssolo3-white.png"), 
-        (Model::BeatsStudio3, _) => Some("beatsstudio3-black.png"), 
-        (Model::BeatsX, _) => Some("beatsx-black.png"),
-        (Model::AirPodsMax, _) => Some("airpodsmax-white.png"), // space-gray, pink green, sky-blue
-        (Model::PowerbeatsPro, _) => Some("powerbeatspro-black.png"), // ivory, navy, black
-        (Model::PowerBeats3, _) => Some("powerbeats3-black.png"),
-        (Model::Unknown(_), _) => None,
-        _ => None
-    }
-}
-
-fn summary_from(msg: &PairedMessage) -> String {
-    format!("{:?}", msg.model)
-}
-
-fn body_from(msg: &PairedMessage) -> String {
-    let level_symbol = |battery| {
-        match battery {
-            Battery::Level(0) | Battery::Level(1) => "",
-            Battery::Level(2) | Battery::Level(3) => "",
-            Battery::Level(4) | Battery::Level(5) => "",
-            Battery::Level(6) | Battery::Level(7) => "",
-            Battery::Level(8) | Battery::Level(9) | Battery::Level(10) => "",
-            _ => "",
+fn update_bluez_batteries(devices: &[bluez::Device], config: &Config, batteries: &mut BatteryModel) {
+    // a disconnected device keeps its last value until it becomes stale
+    for device in &config.device {
+        let percent = devices.iter()
+            .find(|bluez_device| bluez_device.connected && device.has_address(&bluez_device.address))
+            // skip the level published by ourselves
+            .filter(|bluez_device| bluez_device.battery_source.as_deref() != Some(PROVIDER_SOURCE))
+            .and_then(|bluez_device| bluez_device.battery);
+        if let Some(percent) = percent {
+            batteries.update_bluez(device, percent);
+        }
+    }
+}
+
+/* publishes the proximity level of connected devices which have no battery in BlueZ */
+async fn publish_batteries(provider: &mut BatteryProvider, devices: &[bluez::Device], config: &Config, batteries: &BatteryModel) {
+    for bluez_device in devices {
+        let percent = config.device.iter()
+            .find(|device| device.has_address(&bluez_device.address))
+            .and_then(|device| batteries.get(device))
+            .and_then(|battery| battery.proximity_headset())
+            .filter(|reading| reading.is_fresh())
+            .map(|reading| reading.percent);
+        let published = provider.is_published(&bluez_device.path);
+        let result = match percent {
+            Some(percent) if bluez_device.connected && (published || bluez_device.battery.is_none()) => {
+                provider.publish(&bluez_device.path, percent).await
+            }
+            _ => provider.remove(&bluez_device.path).await,
+        };
+        if let Err(err) = result {
+            eprintln!("battery provider: {}", err);
         }
+    }
+}
+
+fn show_left_behind_alerts(tracker: &mut Tracker, laptop_active: bool, digest: &mut Digest, notifier: &mut dyn Notifier) {
+    for alert in tracker.check(laptop_active) {
+        let mut notification = notify_rust::Notification::new();
+        notification.summary(alert.summary().as_str());
+        notification.body(alert.body().as_str());
+        notification.urgency(notify_rust::Urgency::Critical);
+        show(notification, Rule::LeftBehind, digest, notifier);
+    }
+}
+
+/* the public address of the bonded or the registered device */
+fn public_address<H>(card: &Card<H>, config: &Config) -> Option<String> {
+    card.identity.as_ref().map(|identity| identity.address.clone())
+        .or_else(|| config.device(card.device.as_deref()?)?.address.clone())
+}
+
+/* notification of a nearby card, which is built anew for every update */
+fn card_notification(subject: &Subject, templates: &Templates, artwork: &mut Artwork, config: &Config, tag: &str,
+    notifier: &dyn Notifier) -> notify_rust::Notification {
+    let mut notification = notify_rust::Notification::new();
+    artwork.apply(&mut notification, subject.msg);
+    battery_hints(&mut notification, subject.msg, &config.nearby, tag);
+    notification.summary(templates.summary(subject).as_str());
+    notification.body(templates.body(subject).as_str());
+    notification.timeout(notify_rust::Timeout::Default);
+    if notifier.has_actions() {
+        let connected = subject.identity.map(|identity| identity.connected);
+        let has_key = device_key(subject.device, subject.identity).is_some();
+        card_actions(&mut notification, connected, has_key);
+    }
+    notification
+}
+
+/* shows the cards again, which went to the terminal while the notification server was missing */
+fn reshow_cards(cards: &mut Cards<Option<Id>>, templates: &Templates, artwork: &mut Artwork, config: &Config,
+    batteries: &BatteryModel, notifier: &mut dyn Notifier) {
+    for card in cards.iter_mut().filter(|card| card.handle.is_none()) {
+        let device = card.device.as_deref();
+        let battery = device.and_then(|name| config.device(name)).and_then(|device| batteries.get(device));
+        let subject = Subject { msg: &card.msg, device, identity: card.identity.as_ref(), rssi: card.rssi, battery };
+        let notification = card_notification(&subject, templates, artwork, config, &card.tag(), notifier);
+        card.handle = notifier.show(&notification);
+    }
+}
+
+/* shows a step of the connection, following steps replace the shown one */
+fn show_progress(notifier: &mut dyn Notifier, shown: &mut HashMap<String, Id>, address: String, step: &str) {
+    let mut notification = notify_rust::Notification::new();
+    notification.summary(format!("Connecting {}", address).as_str());
+    notification.body(step);
+    notification.timeout(notify_rust::Timeout::Default);
+    let id = match shown.get(&address) {
+        Some(id) => notifier.update(*id, &notification),
+        None => notifier.show(&notification),
+    };
+    // a later attempt starts with a new notification
+    let last = step == PairingStep::Connected.to_string() || step.starts_with("Failed:");
+    match id {
+        Some(id) if !last => shown.insert(address, id),
+        _ => shown.remove(&address),
     };
+}
 
-    let charge_symbol = |is_charging: bool| if is_charging {
-        ""
-    } else {
-        ""
+/* the mute entry of the tray follows the registry */
+async fn update_tray_muted(tray: Option<&mut Tray>, device: Option<&DeviceConfig>, registry: &Registry) {
+    if let (Some(tray), Some(device)) = (tray, device) {
+        let muted = registry.is_muted(&device.name, chrono::Local::now().date_naive());
+        if let Err(err) = tray.set_muted(muted).await {
+            eprintln!("tray: {}", err);
+        }
+    }
+}
+
+/* the steps are sent back through the channel, together with the address */
+async fn connect_device(bluez: Option<BlueZ>, address: String, progress: mpsc::UnboundedSender<(String, String)>) {
+    let report = |step: String| {
+        let _ = progress.send((address.clone(), step));
+    };
+    let bluez = match bluez {
+        Some(bluez) => bluez,
+        None => {
+            report("BlueZ is not available".to_string());
+            return;
+        }
     };
+    let (tx, mut steps) = mpsc::unbounded_channel();
+    let pairing = bluez.pair_and_connect(&address, move |step| {
+        let _ = tx.send(step);
+    });
+    tokio::pin!(pairing);
 
-    let level_text = |battery| {
-        match battery {
-            Battery::Level(v) => format!("{}% ", v*10),
-            Battery::None => "".to_string()
+    let result = loop {
+        tokio::select! {
+            result = &mut pairing => break result,
+            Some(step) = steps.recv() => report(step.to_string()),
         }
     };
+    match result {
+        Ok(()) => report(PairingStep::Connected.to_string()),
+        Err(err) => report(format!("Failed: {}", err)),
+    }
+}
 
-    let mut body = String::from("");
-    if msg.left_battery_level != Battery::None {
-        body.push_str(format!("Left: {}{} {}\n", 
-            level_text(msg.left_battery_level), 
-            level_symbol(msg.left_battery_level), 
-            charge_symbol(msg.left_charging)).as_str());
+async fn run_command(bluez: BlueZ, command: Command) {
+    let result = match &command {
+        Command::Connect { address } => bluez.connect(address).await,
+        Command::Disconnect { address } => bluez.disconnect(address).await,
     };
-    if msg.right_battery_level != Battery::None {
-        body.push_str(format!("Right: {}{} {}\n", 
-            level_text(msg.right_battery_level), 
-            level_symbol(msg.right_battery_level), 
-            charge_symbol(msg.right_charging)).as_str());
+    if let Err(err) = result {
+        eprintln!("{:?} failed: {}", command, err);
+    }
+}
+
+async fn nearby_mode(adapter: Adapter, config: Config) -> Result<(), Box<dyn Error>> {
+    /* registered devices are tracked even if they are out of range */
+    let devices = config.device.clone();
+    let events = filter_events(adapter, move |adv| {
+        adv.rssi >= MIN_RSSI || match &adv.event {
+            ProximityEvent::Paired(msg) => devices.iter().any(|device| device.matches(msg)),
+            ProximityEvent::Pairing(_) => false,
+        }
+    }).await?;
+    let (tx, responses) = mpsc::channel(8);
+    let notifier = notifier::from_config(&config.notifier, tx).await;
+    let services = Services::connect(&config).await;
+    nearby(events, config, services, notifier, responses).await
+}
+
+/* the headset shown in the tray */
+fn tray_device(config: &Config) -> Option<&DeviceConfig> {
+    config.tray.device.as_deref()
+        .and_then(|name| config.device(name))
+        .or_else(|| config.device.first())
+}
+
+/* Services and state of nearby mode outside of the configuration. The
+ * services are missing if they are not available. */
+struct Services<B> {
+    bluez: Option<BlueZ>,
+    logind: Option<Logind>,
+    mpris: Option<Mpris>,
+    availability: Option<Availability>,
+    tray: Option<Tray>,
+    tray_actions: mpsc::Receiver<TrayAction>,
+    registry: Registry,
+    audio: B,
+}
+
+impl Services<Pactl> {
+    /* the services of the system and the session bus */
+    async fn connect(config: &Config) -> Services<Pactl> {
+        let bluez = match BlueZ::system().await {
+            Ok(bluez) => Some(bluez),
+            Err(err) => {
+                eprintln!("BlueZ is not available: {}", err);
+                None
+            }
+        };
+        let logind = match Logind::system().await {
+            Ok(logind) => Some(logind),
+            Err(err) => {
+                eprintln!("logind is not available: {}", err);
+                None
+            }
+        };
+        let mpris = match Mpris::session().await {
+            Ok(mpris) => Some(mpris),
+            Err(err) => {
+                eprintln!("MPRIS is not available: {}", err);
+                None
+            }
+        };
+        let availability = match Availability::session(logind.clone()).await {
+            Ok(availability) => Some(availability),
+            Err(err) => {
+                eprintln!("session bus is not available: {}", err);
+                None
+            }
+        };
+        let (tray_tx, tray_actions) = mpsc::channel(8);
+        if let Some(device) = tray_device(config).filter(|_| config.tray.enabled) {
+            if Irk::for_device(device).is_none() {
+                eprintln!("no IRK of {} is available, the tray follows the only bonded {:?}", device.name, device.model);
+            }
+        }
+        let tray = match tray_device(config) {
+            Some(device) if config.tray.enabled => match Tray::session(device, Levels::new(&config.tray, &config.nearby), tray_tx).await {
+                Ok(tray) => Some(tray),
+                Err(err) => {
+                    eprintln!("tray is not available: {}", err);
+                    None
+                }
+            },
+            _ => None,
+        };
+        Services {
+            bluez,
+            logind,
+            mpris,
+            availability,
+            tray,
+            tray_actions,
+            registry: Registry::load(),
+            audio: Pactl,
+        }
+    }
+}
+
+/* Nearby mode on any source of advertisements, services and notifier,
+ * e.g. replayed advertisements, fakes on a private bus and the recorder. */
+async fn nearby<B: AudioBackend>(
+    mut events: impl Stream<Item = Advertisement> + Unpin,
+    config: Config,
+    services: Services<B>,
+    mut notifier: Box<dyn Notifier>,
+    mut responses: mpsc::Receiver<(Id, Response)>,
+) -> Result<(), Box<dyn Error>> {
+    let Services { bluez, logind, mut mpris, availability, mut tray, mut tray_actions, mut registry, audio } = services;
+    /* cards which went to the fallback of the notification server have no id */
+    let mut cards: Cards<Option<Id>> = Cards::new(config.nearby.max_cards);
+    let notifier = notifier.as_mut();
+    let templates = Templates::new(&config.template)?;
+    let mut artwork = Artwork::new(&config.artwork);
+    for problem in artwork.check(&config.device) {
+        eprintln!("artwork: {}", problem);
+    }
+
+    let mut batteries = BatteryModel::new(&config);
+    let mut bluez_poll = tokio::time::interval(BLUEZ_POLL);
+    let mut battery_provider = match &bluez {
+        Some(bluez) if config.bluez.battery_provider => match BatteryProvider::register(bluez).await {
+            Ok(provider) => Some(provider),
+            Err(err) => {
+                eprintln!("battery provider is not available: {}", err);
+                None
+            }
+        },
+        _ => None,
     };
-    if msg.case_battery_level != Battery::None {
-        body.push_str(format!("Case: {}{} {}\n", 
-            level_text(msg.case_battery_level), 
-            level_symbol(msg.case_battery_level), 
-            charge_symbol(msg.case_charging)).as_str());
+
+    /* the headset used for meetings */
+    let meeting_device = config.calendar.device.as_deref()
+        .and_then(|name| config.device(name))
+        .or_else(|| config.device.first())
+        .cloned();
+    let mut meeting_check = MeetingCheck::new(config.calendar.clone());
+    let mut tracker = Tracker::new(config.left_behind.clone());
+    /* registered devices with a readable IRK */
+    let keyed: HashSet<String> = config.device.iter()
+        .filter(|device| Irk::for_device(device).is_some())
+        .map(|device| device.name.clone())
+        .collect();
+    let mut prompts = Prompts::default();
+    let mut auto_connect = AutoConnect::new(config.auto_connect.clone());
+    let mut identities = Identities::default();
+    let mut ear_detection = EarDetection::new(config.ear_detection.clone());
+    let mut audio_switch = AudioSwitch::new(config.audio.clone(), audio);
+
+    /* the headset of the owner, which must be resolvable by its IRK */
+    let lock_device = config.lock.device.as_deref()
+        .and_then(|name| config.device(name))
+        .or_else(|| config.device.first());
+    let mut presence_lock = match lock_device {
+        Some(device) if config.lock.enabled => match (Irk::for_device(device), &logind) {
+            (Some(irk), Some(_)) => Some(PresenceLock::new(config.lock.clone(), irk)),
+            (None, _) => {
+                eprintln!("session lock requires the IRK of {}", device.name);
+                None
+            }
+            (_, None) => None,
+        },
+        _ => None,
     };
-    body
-}
+    let mut lock_poll = tokio::time::interval(LOCK_POLL);
 
+    let mut digest = Digest::new(config.do_not_disturb.clone(), config.quiet_hours.clone());
+    let mut availability_poll = tokio::time::interval(AVAILABILITY_POLL);
 
-async fn nearby_mode(adapter: Adapter) -> Result<(), Box<dyn Error>> {
-    let mut events = filter_events(adapter, Mode::Nearby).await?;
-    let mut current_handle: Option<notify_rust::NotificationHandle> = None;
+    let tray_device = tray_device(&config).cloned();
+    let tray_irk = tray_device.as_ref().is_some_and(|device| keyed.contains(&device.name));
+    let (progress_tx, mut progress) = mpsc::unbounded_channel();
+    let mut progress_shown = HashMap::new();
+    let mut tick = tokio::time::interval(TICK);
 
-    while let Some(event) = events.next().await {
-        match event {
+    loop {
+        let adv = tokio::select! {
+            Some((id, response)) = responses.recv() => {
+                if let Some(UserAction::Connect { address }) = prompts.respond(id, &response) {
+                    tokio::spawn(connect_device(bluez.clone(), address, progress_tx.clone()));
+                }
+                let action = match &response {
+                    Response::Action(key) => CardAction::from_key(key),
+                    Response::Closed => None,
+                };
+                let is_clicked = |card: &Card<Option<Id>>| card.handle == Some(id);
+                match action {
+                    Some(CardAction::Connect | CardAction::Disconnect) => {
+                        let address = cards.find_by(is_clicked).and_then(|card| public_address(card, &config));
+                        if let (Some(address), Some(bluez)) = (address, &bluez) {
+                            let command = match action {
+                                Some(CardAction::Connect) => Command::Connect { address },
+                                _ => Command::Disconnect { address },
+                            };
+                            tokio::spawn(run_command(bluez.clone(), command));
+                        }
+                    }
+                    Some(CardAction::Mute | CardAction::Hide) => {
+                        if let Some(card) = cards.remove_by(is_clicked) {
+                            // the buttons are only offered for a device with a key
+                            let result = match (action, card.key()) {
+                                (_, None) => Ok(()),
+                                (Some(CardAction::Mute), Some(key)) => registry.mute(&key, chrono::Local::now().date_naive()),
+                                (_, Some(key)) => registry.hide(&key),
+                            };
+                            if let Err(err) = result {
+                                eprintln!("device registry: {}", err);
+                            }
+                            if let Some(id) = card.handle {
+                                notifier.close(id);
+                            }
+                        }
+                        update_tray_muted(tray.as_mut(), tray_device.as_ref(), &registry).await;
+                    }
+                    Some(CardAction::Details) => {
+                        if let Some(card) = cards.find_by(is_clicked) {
+                            let battery = card.device.as_deref()
+                                .and_then(|name| config.device(name))
+                                .and_then(|device| batteries.get(device));
+                            notifier.show(&details(card, battery));
+                        }
+                    }
+                    None => (),
+                }
+                continue;
+            }
+            Some(action) = tray_actions.recv() => {
+                if let (Some(tray), Some(device)) = (&mut tray, &tray_device) {
+                    match action {
+                        TrayAction::Connect | TrayAction::Disconnect => {
+                            let address = tray.card().and_then(|card| public_address(card, &config))
+                                .or_else(|| device.address.clone());
+                            if let (Some(address), Some(bluez)) = (address, &bluez) {
+                                let command = match action {
+                                    TrayAction::Connect => Command::Connect { address },
+                                    _ => Command::Disconnect { address },
+                                };
+                                tokio::spawn(run_command(bluez.clone(), command));
+                            }
+                        }
+                        TrayAction::Details => {
+                            if let Some(card) = tray.card() {
+                                notifier.show(&details(card, batteries.get(device)));
+                            }
+                        }
+                        TrayAction::Monitor => tray::open_monitor(&config.tray.terminal),
+                        TrayAction::Mute | TrayAction::Unmute => {
+                            let result = match action {
+                                TrayAction::Mute => registry.mute(&device.name, chrono::Local::now().date_naive()),
+                                _ => registry.unmute(&device.name),
+                            };
+                            if let Err(err) = result {
+                                eprintln!("device registry: {}", err);
+                            }
+                            if action == TrayAction::Mute {
+                                if let Some(id) = cards.remove_by(|card| card.key().as_ref() == Some(&device.name)).and_then(|card| card.handle) {
+                                    notifier.close(id);
+                                }
+                            }
+                        }
+                    }
+                }
+                update_tray_muted(tray.as_mut(), tray_device.as_ref(), &registry).await;
+                continue;
+            }
+            Some((address, step)) = progress.recv() => {
+                show_progress(notifier, &mut progress_shown, address, &step);
+                continue;
+            }
+            event = events.next() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            _ = tick.tick() => {
+                if meeting_check.is_enabled() {
+                    let headset = match &meeting_device {
+                        Some(device) => batteries.get(device),
+                        None => batteries.lowest_unregistered(),
+                    };
+                    show_meeting_warnings(&mut meeting_check, headset, &mut digest, notifier);
+                }
+                if tracker.is_enabled() {
+                    // without logind the laptop is considered to be in use
+                    let laptop_active = match &logind {
+                        Some(logind) => !logind.is_idle().await.unwrap_or(false),
+                        None => true,
+                    };
+                    show_left_behind_alerts(&mut tracker, laptop_active, &mut digest, notifier);
+                }
+                // devices which went away without closing the lid
+                for expired in cards.expire() {
+                    if let Some(id) = expired.handle {
+                        notifier.close(id);
+                    }
+                }
+                prompts.expire(notifier);
+                // the mute ends with the day
+                update_tray_muted(tray.as_mut(), tray_device.as_ref(), &registry).await;
+                continue;
+            }
+            _ = bluez_poll.tick(), if bluez.is_some() => {
+                if let Some(bluez) = &bluez {
+                    match bluez.devices().await {
+                        Ok(devices) => {
+                            identities.update(&devices, &config);
+                            update_bluez_batteries(&devices, &config, &mut batteries);
+                            let connected = tray_device.as_ref()
+                                .and_then(|device| devices.iter().find(|bluez_device| device.has_address(&bluez_device.address)))
+                                .map(|bluez_device| bluez_device.connected);
+                            if let (Some(tray), Some(connected)) = (&mut tray, connected) {
+                                if let Err(err) = tray.set_connected(connected).await {
+                                    eprintln!("tray: {}", err);
+                                }
+                            }
+                            if let Some(provider) = &mut battery_provider {
+                                publish_batteries(provider, &devices, &config, &batteries).await;
+                            }
+                        }
+                        Err(err) => eprintln!("BlueZ devices: {}", err),
+                    }
+                }
+                continue;
+            }
+            _ = availability_poll.tick() => {
+                let away = match &availability {
+                    _ if config.quiet_hours.is_quiet(&chrono::Local::now()) => Some(Away::QuietHours),
+                    Some(availability) if config.do_not_disturb.enabled => availability.away().await,
+                    _ => None,
+                };
+                if let Some(notification) = digest.set_away(away) {
+                    notifier.show(&notification);
+                }
+                if notifier.retry() && !digest.is_holding(Rule::Nearby, false) {
+                    reshow_cards(&mut cards, &templates, &mut artwork, &config, &batteries, notifier);
+                }
+                continue;
+            }
+            _ = lock_poll.tick(), if presence_lock.is_some() => {
+                if let (Some(presence_lock), Some(logind)) = (&mut presence_lock, &logind) {
+                    if presence_lock.check() {
+                        if let Err(err) = logind.lock().await {
+                            eprintln!("session lock failed: {}", err);
+                        }
+                    }
+                }
+                continue;
+            }
+        };
+        let nearby = adv.rssi >= MIN_RSSI;
+        match adv.event {
             ProximityEvent::Paired(msg) => {
+                if let Some(presence_lock) = &mut presence_lock {
+                    presence_lock.update(&adv.address, &msg, adv.rssi);
+                }
+                let device = config.device.iter().find(|device| device.matches(&msg));
+                let identity = identities.resolve(&adv.address, &msg);
+                let identity = identity.as_ref();
+                if config.device.is_empty() {
+                    batteries.update_unregistered(&msg);
+                }
+                if let Some(device) = device {
+                    batteries.update_proximity(device, &msg);
+                    if is_tracked_report(device, keyed.contains(&device.name), identity) {
+                        tracker.update(device, &msg, adv.rssi);
+                    }
+                    if let (Some(command), Some(bluez)) = (auto_connect.update(device, identity, &msg, nearby), &bluez) {
+                        tokio::spawn(run_command(bluez.clone(), command));
+                    }
+                    // identical AirPods of somebody else must not control the player or the sink
+                    if is_own_report(device, identity) {
+                        if let (Some(command), Some(mpris)) = (ear_detection.update(device, &msg), &mut mpris) {
+                            let result = match command {
+                                MediaCommand::Pause => mpris.pause().await,
+                                MediaCommand::Resume => mpris.resume().await,
+                            };
+                            if let Err(err) = result {
+                                eprintln!("{:?} failed: {}", command, err);
+                            }
+                        }
+                        if let Err(err) = audio_switch.update(device, &msg).await {
+                            eprintln!("audio switch failed: {}", err);
+                        }
+                    }
+                }
+                let name = device.map(|device| device.name.as_str());
+                let battery = device.and_then(|device| batteries.get(device));
+                let subject = Subject { msg: &msg, device: name, identity, rssi: adv.rssi, battery };
+                if let (Some(tray), Some(device)) = (&mut tray, &tray_device) {
+                    if is_tray_report(device, tray_irk, identity) {
+                        if let Err(err) = tray.update(identity, adv.address, adv.rssi, msg).await {
+                            eprintln!("tray: {}", err);
+                        }
+                    }
+                }
+                let key = device_key(name, identity);
+                let tag = device_tag(key.as_deref(), &adv.address);
                 match msg.lid {
+                    Lid::Open(_) if !nearby => (),
                     Lid::Open(_) => {
-                        if let Some(mut handle) = current_handle {
-                            // notification update
-                            handle.body(body_from(&msg).as_str());
-                            handle.summary(summary_from(&msg).as_str());
-                            if let Some(path) = image_from(&msg) {            
-                                handle.image(format!("./res/{}", path));
+                        if let Some(card) = cards.find(name, &adv.address, &msg) {
+                            // notification update, which would show the notification again
+                            if let (Some(id), false) = (card.handle, digest.is_holding(Rule::Nearby, false)) {
+                                let notification = card_notification(&subject, &templates, &mut artwork, &config, &tag, notifier);
+                                card.handle = notifier.update(id, &notification);
                             }
-                            handle.update();
-                            current_handle = Some(handle);
+                            card.update(identity.cloned(), adv.address, adv.rssi, msg);
+                        } else if key.as_deref().is_some_and(|key| registry.is_silenced(key, chrono::Local::now().date_naive())) {
+                            // muted or hidden by the buttons of an earlier card
                         } else {
                             // new notification
-                            let mut notification = notify_rust::Notification::new();
-                            
-                            if let Some(path) = image_from(&msg) {            
-                                notification.image(format!("./res/{}", path));
-                            }                    
-                            notification.summary(summary_from(&msg).as_str());
-                            notification.body(body_from(&msg).as_str());
-                            notification.timeout(notify_rust::Timeout::Default);
-                            current_handle = Some(notification.show().unwrap());            
+                            let mut notification = card_notification(&subject, &templates, &mut artwork, &config, &tag, notifier);
+                            if !digest.admit(&mut notification, Rule::Nearby) {
+                                continue;
+                            }
+                            let handle = notifier.show(&notification);
+                            let card = Card::new(name.map(String::from), identity.cloned(), adv.address, adv.rssi, msg, handle);
+                            for evicted in cards.insert(card) {
+                                if let Some(id) = evicted.handle {
+                                    notifier.close(id);
+                                }
+                            }
                         }
                     }
                     Lid::Closed(_) => {
-                        if let Some(handle) = current_handle {
-                            handle.close();                            
+                        if let Some(id) = cards.remove(name, &adv.address, &msg).and_then(|card| card.handle) {
+                            notifier.close(id);
                         }
-                        current_handle = None;
                     }
                 }
             },
             ProximityEvent::Pairing(msg) => {
-                    // new notification
-                    let mut notification = notify_rust::Notification::new();
-                    notification.action("connect", "Connect");
-                    notification.action("default", "default");
-                    notification.hint(notify_rust::Hint::Resident(true));
-                    notification.summary("");
-                    notification.body("New device! Want to connect?");
-                    notification.timeout(notify_rust::Timeout::Default);
-                    notification.show()
-                    .unwrap()
-                    .wait_for_action(|action| match action {
-                        "default" => println!("default"),
-                        "connect" => println!("connect"),
-                        _ => (),
-                    });
+                let mut notification = notify_rust::Notification::new();
+                notification.action("connect", "Connect");
+                notification.action("default", "default");
+                notification.hint(notify_rust::Hint::Resident(true));
+                notification.hint(notify_rust::Hint::Category("device.added".to_string()));
+                notification.summary(msg.device_model.to_string().as_str());
+                notification.body(format!("New {} device! Want to connect?", msg.device_color).as_str());
+                notification.timeout(notify_rust::Timeout::Default);
+                if digest.admit(&mut notification, Rule::Pairing) {
+                    // without actions, the prompt is closed after its timeout
+                    prompts.prompt(notifier, msg.address(), notification);
+                }
             }
-            _ => ()
         }
-    }    
+        prompts.expire(notifier);
+    }
     Ok(())
 }
 
-async fn monitor_mode(adapter: Adapter) -> Result<(), Box<dyn Error>> {
+async fn monitor_mode(adapter: Adapter, config: Config) -> Result<(), Box<dyn Error>> {
+    let bluez = BlueZ::system().await.ok();
+    let mut identities = Identities::default();
+    let mut bluez_poll = tokio::time::interval(BLUEZ_POLL);
+
+    print!("{0:^24} | ", "Device");
     print!("{0:^16} | ", "Model");
     print!("{0:^10} | ", "Color");
     print!("{0:^10} | ", "Lid");
@@ -165,36 +721,126 @@ async fn monitor_mode(adapter: Adapter) -> Result<(), Box<dyn Error>> {
     print!("{0:^8} | ", "in Case");
     println!("{0:^14} | ", "Part");        
 
-    let mut events = filter_events(adapter, Mode::Monitor).await?;
-    while let Some(event) = events.next().await {
-        match event {
-            ProximityEvent::Paired(msg) => {
-                print!("{0:^16} | ", msg.model.to_string());
-                print!("{0:^10} | ", msg.color.to_string());
-                print!("{0:^10} | ", msg.lid.to_string());
-                print!("{0:^10} | ", msg.case_battery_level.to_string());
-                print!("{0:^10} | ", msg.left_battery_level.to_string());
-                print!("{0:^10} | ", msg.right_battery_level.to_string());
-                print!("{0:^13} | ", msg.case_charging);
-                print!("{0:^13} | ", msg.left_charging);
-                print!("{0:^14} | ", msg.right_charging);
-                print!("{0:^8} | ", msg.plugged_in_ear.to_string());
-                print!("{0:^8} | ", msg.plugged_in_case.to_string());
-                println!("{0:^14} | ", msg.part.to_string());
-            }
-            _ => ()
-            
+    let mut events = filter_events(adapter, |_| true).await?;
+    loop {
+        let adv = tokio::select! {
+            adv = events.next() => match adv {
+                Some(adv) => adv,
+                None => break,
+            },
+            _ = bluez_poll.tick(), if bluez.is_some() => {
+                if let Some(bluez) = &bluez {
+                    if let Ok(devices) = bluez.devices().await {
+                        identities.update(&devices, &config);
+                    }
+                }
+                continue;
+            }
+        };
+        if let ProximityEvent::Paired(msg) = adv.event {
+            // a connected device is marked with an asterisk
+            let device = match identities.resolve(&adv.address, &msg) {
+                Some(identity) if identity.connected => format!("{}*", identity.alias),
+                Some(identity) => identity.alias,
+                None => adv.address.to_string(),
+            };
+            print!("{0:^24} | ", device);
+            print!("{0:^16} | ", msg.model.to_string());
+            print!("{0:^10} | ", msg.color.to_string());
+            print!("{0:^10} | ", msg.lid.to_string());
+            print!("{0:^10} | ", msg.case_battery_level.to_string());
+            print!("{0:^10} | ", msg.left_battery_level.to_string());
+            print!("{0:^10} | ", msg.right_battery_level.to_string());
+            print!("{0:^13} | ", msg.case_charging);
+            print!("{0:^13} | ", msg.left_charging);
+            print!("{0:^14} | ", msg.right_charging);
+            print!("{0:^8} | ", msg.plugged_in_ear.to_string());
+            print!("{0:^8} | ", msg.plugged_in_case.to_string());
+            println!("{0:^14} | ", msg.part.to_string());
+        }
+    }
+    Ok(())
+}
+
+async fn locate_mode(adapter: Adapter, device: DeviceConfig, with_beep: bool) -> Result<(), Box<dyn Error>> {
+    println!("Locating {}, move around and follow the gauge.", device.name);
+
+    /* no range limitation, only the chosen device */
+    let target = device.clone();
+    let irk = Irk::for_device(&device);
+    let mut events = filter_events(adapter, move |adv| is_located(&target, irk.as_ref(), adv)).await?;
+
+    /* each bud reports its own signal */
+    let mut locators: HashMap<Part, Locator> = HashMap::new();
+    let mut beep = Box::pin(tokio::time::sleep(Duration::from_secs(1)));
+
+    loop {
+        tokio::select! {
+            adv = events.next() => match adv {
+                Some(Advertisement { rssi, event: ProximityEvent::Paired(msg), .. }) => {
+                    let locator = locators.entry(msg.part).or_default();
+                    let smoothed = locator.update(rssi);
+                    print!("\r\x1b[2K{} {:>6.1} dBm {} {}",
+                        locator.gauge(), smoothed, locator.trend(), msg.part);
+                    std::io::stdout().flush()?;
+                }
+                Some(_) => (),
+                None => break,
+            },
+            _ = &mut beep => {
+                // the nearest bud sets the pace
+                let interval = locators.values().map(Locator::beep_interval).min();
+                if with_beep && interval.is_some() {
+                    print!("\x07");
+                    std::io::stdout().flush()?;
+                }
+                beep.as_mut().reset(tokio::time::Instant::now() + interval.unwrap_or_else(|| Locator::default().beep_interval()));
+            }
         }
     }
+    println!();
     Ok(())
 }
 
+/* reports resolved by the IRK to the bonded registered device, which may control the machine */
+fn is_own_report(device: &DeviceConfig, identity: Option<&Identity>) -> bool {
+    identity.is_some_and(|identity| identity.resolved && device.has_address(&identity.address))
+}
+
+/* with a readable IRK only the bonded device itself is tracked, otherwise
+ * any device of the registered model and color */
+fn is_tracked_report(device: &DeviceConfig, irk: bool, identity: Option<&Identity>) -> bool {
+    !irk || is_own_report(device, identity)
+}
+
+/* An identical model of somebody else must not change the tray. Without a
+ * readable IRK the tray falls back to the guess of the bonded device of the
+ * model, as the key is only readable by root. */
+fn is_tray_report(device: &DeviceConfig, irk: bool, identity: Option<&Identity>) -> bool {
+    identity.is_some_and(|identity| (identity.resolved || !irk) && device.has_address(&identity.address))
+}
+
+/* with a known key only the reports of the bonded device itself, otherwise
+ * any device of the registered model and color */
+fn is_located(device: &DeviceConfig, irk: Option<&Irk>, adv: &Advertisement) -> bool {
+    match &adv.event {
+        ProximityEvent::Paired(msg) => match irk {
+            Some(irk) => irk.resolves(&adv.address),
+            None => device.matches(msg),
+        },
+        ProximityEvent::Pairing(_) => false,
+    }
+}
+
 async fn get_central(manager: &Manager) -> Adapter {
     let adapters = manager.adapters().await.unwrap();
-    adapters.into_iter().nth(0).unwrap()
+    adapters.into_iter().next().unwrap()
 }
 
-pub async fn filter_events(adapter: Adapter, mode: Mode) -> Result<impl Stream<Item = ProximityEvent>, Box<dyn Error>> {
+pub async fn filter_events<F>(adapter: Adapter, filter: F) -> Result<impl Stream<Item = Advertisement>, Box<dyn Error>>
+where
+    F: Fn(&Advertisement) -> bool + Send + 'static,
+{
     let (tx, rx) = mpsc::channel(1);
     //let mut known_devices: HashMap<bluer::Address, PairedMessage> = HashMap::new();
     let mut events = adapter.events().await?;
@@ -215,10 +861,15 @@ pub async fn filter_events(adapter: Adapter, mode: Mode) -> Result<impl Stream<I
                     .unwrap().rssi;
 
                 if let Some(rssi) = property {
-                    if mode == Mode::Monitor || rssi >= MIN_RSSI {
-                        /* unpack manufacturer data and parse it */
-                        if let Some(msg) = ProximityEvent::from_manufacturer_data(manufacturer_data) {
-                            let _ = tx.send(msg).await;
+                    /* unpack manufacturer data and parse it */
+                    if let Some(event) = ProximityEvent::from_manufacturer_data(manufacturer_data) {
+                        let adv = Advertisement {
+                            address: peripheral.address(),
+                            rssi,
+                            event,
+                        };
+                        if filter(&adv) {
+                            let _ = tx.send(adv).await;
                         }
                     }
                 }
@@ -234,14 +885,310 @@ async fn main() -> Result<(), Box<dyn Error>>  {
 
     let manager = Manager::new().await?;
     let central = get_central(&manager).await;
-    let with_monitor = env::args().any(|arg| arg == "--monitor");    
+    let mode = if env::args().nth(1).as_deref() == Some("locate") {
+        Mode::Locate
+    } else if env::args().any(|arg| arg == "--monitor") {
+        Mode::Monitor
+    } else {
+        Mode::Nearby
+    };
+    let config_path = env::args()
+        .skip_while(|arg| arg != "--config")
+        .nth(1)
+        .map(PathBuf::from);
+    let config = Config::load(config_path)?;
 
     // start scanning for devices
     central.start_scan(ScanFilter::default()).await?;
-    if with_monitor {
-        monitor_mode(central).await?;
-    } else {
-        nearby_mode(central).await?;
+    match mode {
+        Mode::Monitor => monitor_mode(central, config).await?,
+        Mode::Nearby => nearby_mode(central, config).await?,
+        Mode::Locate => {
+            // locate [<device name>] [--beep]
+            let name = env::args().skip(2)
+                .take_while(|arg| !arg.starts_with("--"))
+                .next();
+            let device = match &name {
+                Some(name) => config.device(name),
+                None => config.device.first(),
+            }.ok_or("device is not registered in the configuration")?;
+            let with_beep = env::args().any(|arg| arg == "--beep");
+            locate_mode(central, device.clone(), with_beep).await?
+        }
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::Source;
+    use crate::notifier::{Record, Recorder};
+    use crate::proximity::PairedMessage;
+    use crate::proximity::Plugged;
+    use crate::testing::{paired_message, Calls, FakePlayer, MockBlueZ, MockDevice, PrivateBus, RecordingBackend, HEADSET, SPEAKERS};
+    use btleplug::api::BDAddr;
+
+    const IRK: &str = "00112233445566778899aabbccddeeff";
+
+    fn config(devices: &str) -> Config {
+        toml::from_str(devices).unwrap()
+    }
+
+    #[tokio::test]
+    async fn bluez_and_proximity_levels_are_merged() {
+        let config = config("[[device]]\nname = \"Work\"\nmodel = \"AirPodsPro\"\naddress = \"AA:BB:CC:DD:EE:01\"\n\
+            [[device]]\nname = \"Home\"\nmodel = \"AirPodsMax\"\naddress = \"AA:BB:CC:DD:EE:02\"\n");
+        let bus = PrivateBus::start();
+        let mock = MockBlueZ::start(&bus).await;
+        mock.add_device(MockDevice { paired: true, connected: true, ..MockDevice::new("AA:BB:CC:DD:EE:01", "Work AirPods") }, Some(73)).await;
+        // a disconnected device keeps the levels of the advertisements
+        mock.add_device(MockDevice { paired: true, ..MockDevice::new("AA:BB:CC:DD:EE:02", "AirPods Max") }, Some(20)).await;
+
+        let mut batteries = BatteryModel::new(&config);
+        batteries.update_proximity(&config.device[0], &paired_message(8, 6, 10));
+        let devices = BlueZ::new(bus.connect().await).devices().await.unwrap();
+        update_bluez_batteries(&devices, &config, &mut batteries);
+
+        let work = batteries.get(&config.device[0]).unwrap();
+        assert_eq!(work.headset().map(|reading| (reading.percent, reading.source)), Some((73, Source::BlueZ)));
+        assert_eq!(work.right().map(|reading| reading.percent), Some(60));
+        assert_eq!(batteries.get(&config.device[1]).unwrap().headset(), None);
+    }
+
+    #[test]
+    fn locate_follows_the_bonded_device() {
+        let device = config("[[device]]\nname = \"Work\"\nmodel = \"AirPodsPro\"\n").device.remove(0);
+        let irk = Irk::from_hex(IRK).unwrap();
+        let other = Irk::from_hex("ffeeddccbbaa99887766554433221100").unwrap();
+        let report = |address| Advertisement {
+            address,
+            rssi: -70,
+            event: ProximityEvent::Paired(paired_message(5, 5, 5)),
+        };
+        // identical AirPods of somebody else
+        assert!(is_located(&device, None, &report(other.private_address([1, 2, 3]))));
+        assert!(!is_located(&device, Some(&irk), &report(other.private_address([1, 2, 3]))));
+        assert!(!is_located(&device, Some(&irk), &report(BDAddr::from([0x11, 0x22, 0x33, 0x44, 0x55, 0x66]))));
+        assert!(is_located(&device, Some(&irk), &report(irk.private_address([1, 2, 3]))));
+        assert!(is_located(&device, Some(&irk), &report(irk.private_address([7, 8, 9]))));
+    }
+
+    #[test]
+    fn tray_follows_the_resolved_device() {
+        let device = config("[[device]]\nname = \"Work\"\nmodel = \"AirPodsPro\"\naddress = \"AA:BB:CC:DD:EE:01\"\n").device.remove(0);
+        let identity = |address: &str, resolved| Identity {
+            alias: "AirPods".to_string(),
+            address: address.to_string(),
+            connected: true,
+            resolved,
+        };
+        assert!(is_tray_report(&device, true, Some(&identity("aa:bb:cc:dd:ee:01", true))));
+        // guessed by the model or another bonded device
+        assert!(!is_tray_report(&device, true, Some(&identity("AA:BB:CC:DD:EE:01", false))));
+        assert!(!is_tray_report(&device, true, Some(&identity("AA:BB:CC:DD:EE:02", true))));
+        assert!(!is_tray_report(&device, true, None));
+        // the guess is all there is without a readable key
+        assert!(is_tray_report(&device, false, Some(&identity("AA:BB:CC:DD:EE:01", false))));
+        assert!(!is_tray_report(&device, false, Some(&identity("AA:BB:CC:DD:EE:02", false))));
+        assert!(!is_tray_report(&device, false, None));
+    }
+
+    #[test]
+    fn tracker_follows_the_resolved_device_with_a_key() {
+        let device = config("[[device]]\nname = \"Work\"\nmodel = \"AirPodsPro\"\naddress = \"AA:BB:CC:DD:EE:01\"\n").device.remove(0);
+        let identity = |resolved| Identity {
+            alias: "AirPods".to_string(),
+            address: "AA:BB:CC:DD:EE:01".to_string(),
+            connected: true,
+            resolved,
+        };
+        assert!(is_tracked_report(&device, true, Some(&identity(true))));
+        assert!(!is_tracked_report(&device, true, Some(&identity(false))));
+        assert!(!is_tracked_report(&device, true, None));
+        // the model and color is all there is without a key
+        assert!(is_tracked_report(&device, false, None));
+    }
+
+    #[tokio::test]
+    async fn proximity_level_is_provided_for_connected_devices() {
+        let config = config("[[device]]\nname = \"Work\"\nmodel = \"AirPodsPro\"\naddress = \"AA:BB:CC:DD:EE:01\"\n\
+            [[device]]\nname = \"Home\"\nmodel = \"AirPodsPro\"\naddress = \"AA:BB:CC:DD:EE:02\"\n");
+        let bus = PrivateBus::start();
+        let mock = MockBlueZ::start(&bus).await;
+        mock.add_device(MockDevice { paired: true, connected: true, ..MockDevice::new("AA:BB:CC:DD:EE:01", "Work AirPods") }, None).await;
+        // BlueZ knows the level of this one already
+        mock.add_device(MockDevice { paired: true, connected: true, ..MockDevice::new("AA:BB:CC:DD:EE:02", "Home AirPods") }, Some(20)).await;
+
+        let mut batteries = BatteryModel::new(&config);
+        batteries.update_proximity(&config.device[0], &paired_message(8, 6, 10));
+        batteries.update_proximity(&config.device[1], &paired_message(8, 6, 10));
+        let bluez = BlueZ::new(bus.connect().await);
+        let mut provider = BatteryProvider::register(&bluez).await.unwrap();
+        let mut devices = bluez.devices().await.unwrap();
+        publish_batteries(&mut provider, &devices, &config, &batteries).await;
+        let work = devices.iter().find(|device| device.battery.is_none()).unwrap().path.to_string();
+        assert_eq!(mock.provided_batteries().await, [(work, 60)]);
+
+        // withdrawn once the device disconnects
+        devices.iter_mut().for_each(|device| device.connected = false);
+        publish_batteries(&mut provider, &devices, &config, &batteries).await;
+        assert!(mock.provided_batteries().await.is_empty());
+    }
+
+    /* nearby mode without any service of the machine */
+    fn services() -> Services<RecordingBackend> {
+        Services {
+            bluez: None,
+            logind: None,
+            mpris: None,
+            availability: None,
+            tray: None,
+            tray_actions: mpsc::channel(1).1,
+            registry: Registry::open(None),
+            audio: RecordingBackend::new(&[SPEAKERS, HEADSET]),
+        }
+    }
+
+    /* the own headset, which is bonded and resolvable by its IRK */
+    async fn own_headset(bus: &PrivateBus) -> MockBlueZ {
+        let mock = MockBlueZ::start(bus).await;
+        mock.add_device(MockDevice { paired: true, connected: true, ..MockDevice::new("AA:BB:CC:DD:EE:01", "Work AirPods") }, None).await;
+        mock
+    }
+
+    async fn services_on(bus: &PrivateBus) -> Services<RecordingBackend> {
+        Services {
+            bluez: Some(BlueZ::new(bus.connect().await)),
+            mpris: Some(Mpris::new(bus.connect().await)),
+            ..services()
+        }
+    }
+
+    fn own_config() -> Config {
+        config(&format!("[[device]]\nname = \"Work\"\nmodel = \"AirPodsPro\"\naddress = \"AA:BB:CC:DD:EE:01\"\nirk = \"{}\"\n", IRK))
+    }
+
+    fn ears(address: BDAddr, plugged_in_ear: Plugged) -> Advertisement {
+        let msg = PairedMessage { plugged_in_ear, ..paired_message(5, 5, 5) };
+        Advertisement { address, rssi: -50, event: ProximityEvent::Paired(msg) }
+    }
+
+    /* runs nearby mode on the reports, once BlueZ was asked for the bonded devices */
+    async fn replay<B: AudioBackend>(config: Config, services: Services<B>, reports: Vec<Advertisement>) -> Recorder {
+        let (tx, responses) = mpsc::channel(8);
+        let recorder = Recorder::new(tx);
+        let (advertisements, events) = mpsc::channel(8);
+        let replay = async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            for report in reports {
+                advertisements.send(report).await.unwrap();
+            }
+        };
+        let (result, ()) = tokio::join!(
+            nearby(ReceiverStream::new(events), config, services, Box::new(recorder.clone()), responses),
+            replay,
+        );
+        result.unwrap();
+        recorder
+    }
+
+    #[tokio::test]
+    async fn only_the_own_headset_pauses_the_player() {
+        let bus = PrivateBus::start();
+        let calls = Calls::default();
+        let _music = FakePlayer::start(&bus, "music", "Playing", &calls).await;
+        let own = Irk::from_hex(IRK).unwrap().private_address([4, 5, 6]);
+        // identical AirPods of somebody else, which match the registered model
+        let stranger = Irk::from_hex("ffeeddccbbaa99887766554433221100").unwrap().private_address([1, 2, 3]);
+
+        let _bluez = own_headset(&bus).await;
+        replay(own_config(), services_on(&bus).await, vec![ears(stranger, Plugged::Both), ears(stranger, Plugged::None)]).await;
+        assert!(calls.take().is_empty());
+        replay(own_config(), services_on(&bus).await, vec![ears(own, Plugged::Both), ears(own, Plugged::None)]).await;
+        assert_eq!(calls.take(), ["Pause music"]);
+    }
+
+    #[tokio::test]
+    async fn only_the_own_headset_switches_the_sink() {
+        let bus = PrivateBus::start();
+        let _bluez = own_headset(&bus).await;
+        let own = Irk::from_hex(IRK).unwrap().private_address([4, 5, 6]);
+        let stranger = Irk::from_hex("ffeeddccbbaa99887766554433221100").unwrap().private_address([1, 2, 3]);
+        let services = services_on(&bus).await;
+        let calls = services.audio.calls.clone();
+
+        let reports = vec![ears(stranger, Plugged::Both), ears(own, Plugged::None), ears(own, Plugged::Both), ears(stranger, Plugged::None)];
+        replay(own_config(), services, reports).await;
+        // the stranger taking the buds out does not restore the speakers
+        assert_eq!(calls.take(), [format!("default {}", HEADSET)]);
+    }
+
+    /* waits until the notifier got the given number of calls */
+    async fn records(recorder: &Recorder, count: usize) -> Vec<Record> {
+        for _ in 0..500 {
+            if recorder.records().len() >= count {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        recorder.records()
+    }
+
+    #[tokio::test]
+    async fn replayed_advertisements_produce_a_card() {
+        let (tx, responses) = mpsc::channel(8);
+        let recorder = Recorder::new(tx);
+        let (advertisements, events) = mpsc::channel(8);
+        let address = BDAddr::from([0x51, 0, 0, 0, 0, 1]);
+        let report = move |msg| Advertisement { address, rssi: -50, event: ProximityEvent::Paired(msg) };
+        let closed = PairedMessage { lid: Lid::Closed(2), ..paired_message(4, 5, 5) };
+
+        let user = recorder.clone();
+        let replay = async move {
+            advertisements.send(report(paired_message(5, 5, 5))).await.unwrap();
+            advertisements.send(report(paired_message(4, 5, 5))).await.unwrap();
+            records(&user, 2).await;
+            user.click(1, "card-details").await;
+            records(&user, 3).await;
+            advertisements.send(report(closed)).await.unwrap();
+        };
+        let (result, ()) = tokio::join!(
+            nearby(ReceiverStream::new(events), Config::default(), services(), Box::new(recorder.clone()), responses),
+            replay,
+        );
+        result.unwrap();
+
+        let records = recorder.records();
+        let [Record::Shown(1, card), Record::Updated(1, update), Record::Shown(2, details), Record::Closed(1)] = records.as_slice() else {
+            panic!("unexpected notifications: {:?}", records);
+        };
+        // the level of the left bud changed
+        assert_ne!(update.body, card.body);
+        // someone else's device can only be looked at
+        assert_eq!(card.actions, ["card-details", "Details"]);
+        assert!(details.actions.is_empty());
+    }
+
+    #[test]
+    fn progress_ends_with_the_attempt() {
+        let (tx, _responses) = mpsc::channel(8);
+        let mut recorder = Recorder::new(tx);
+        let mut shown = HashMap::new();
+        let address = "AA:BB:CC:DD:EE:01".to_string();
+        show_progress(&mut recorder, &mut shown, address.clone(), &PairingStep::Pairing.to_string());
+        show_progress(&mut recorder, &mut shown, address.clone(), &PairingStep::Connected.to_string());
+        assert!(shown.is_empty());
+        // the next attempt does not update the old notification
+        show_progress(&mut recorder, &mut shown, address.clone(), &PairingStep::Pairing.to_string());
+        show_progress(&mut recorder, &mut shown, address.clone(), "Failed: timeout");
+        assert!(shown.is_empty());
+
+        let ids: Vec<String> = recorder.records().iter().map(|record| match record {
+            Record::Shown(id, _) => format!("shown {}", id),
+            Record::Updated(id, _) => format!("updated {}", id),
+            Record::Closed(id) => format!("closed {}", id),
+        }).collect();
+        assert_eq!(ids, ["shown 1", "updated 1", "shown 2", "updated 2"]);
+    }
+}
@@ -0,0 +1,120 @@
+use zbus::fdo::DBusProxy;
+use zbus::names::OwnedBusName;
+use zbus::{proxy, Connection};
+
+const PLAYER_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const PLAYING: &str = "Playing";
+const PAUSED: &str = "Paused";
+
+#[proxy(interface = "org.mpris.MediaPlayer2.Player", default_path = "/org/mpris/MediaPlayer2")]
+trait Player {
+    fn pause(&self) -> zbus::Result<()>;
+    fn play(&self) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> zbus::Result<String>;
+}
+
+/* Controls the MPRIS players of the session. The connection is injected,
+ * so the client works the same way with a fake player on a private bus. */
+pub struct Mpris {
+    connection: Connection,
+    /* the player paused by us, which may be resumed */
+    paused: Option<OwnedBusName>,
+}
+
+impl Mpris {
+    pub fn new(connection: Connection) -> Mpris {
+        Mpris {
+            connection,
+            paused: None,
+        }
+    }
+
+    pub async fn session() -> zbus::Result<Mpris> {
+        Ok(Mpris::new(Connection::session().await?))
+    }
+
+    async fn player(&self, name: OwnedBusName) -> zbus::Result<PlayerProxy<'static>> {
+        PlayerProxy::builder(&self.connection)
+            .destination(name)?
+            .cache_properties(zbus::proxy::CacheProperties::No)
+            .build()
+            .await
+    }
+
+    async fn status(&self, name: &OwnedBusName) -> zbus::Result<String> {
+        self.player(name.clone()).await?.playback_status().await
+    }
+
+    /* pauses the first playing player */
+    pub async fn pause(&mut self) -> zbus::Result<()> {
+        let names = DBusProxy::new(&self.connection).await?.list_names().await?;
+        for name in names.into_iter().filter(|name| name.starts_with(PLAYER_PREFIX)) {
+            if self.status(&name).await.is_ok_and(|status| status == PLAYING) {
+                self.player(name.clone()).await?.pause().await?;
+                self.paused = Some(name);
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /* resumes the player paused by us, unless the user changed it meanwhile */
+    pub async fn resume(&mut self) -> zbus::Result<()> {
+        if let Some(name) = self.paused.take() {
+            if self.status(&name).await? == PAUSED {
+                self.player(name).await?.play().await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Calls, FakePlayer, PrivateBus};
+
+    #[tokio::test]
+    async fn playing_player_is_paused_and_resumed() {
+        let bus = PrivateBus::start();
+        let calls = Calls::default();
+        let _idle = FakePlayer::start(&bus, "idle", PAUSED, &calls).await;
+        let _music = FakePlayer::start(&bus, "music", PLAYING, &calls).await;
+
+        let mut mpris = Mpris::new(bus.connect().await);
+        mpris.pause().await.unwrap();
+        assert_eq!(calls.take(), ["Pause music"]);
+        mpris.resume().await.unwrap();
+        assert_eq!(calls.take(), ["Play music"]);
+        // only once
+        mpris.resume().await.unwrap();
+        assert!(calls.take().is_empty());
+    }
+
+    #[tokio::test]
+    async fn player_changed_by_the_user_is_not_resumed() {
+        let bus = PrivateBus::start();
+        let calls = Calls::default();
+        let music = FakePlayer::start(&bus, "music", PLAYING, &calls).await;
+
+        let mut mpris = Mpris::new(bus.connect().await);
+        mpris.pause().await.unwrap();
+        FakePlayer::set_status(&music, "Stopped").await;
+        mpris.resume().await.unwrap();
+        assert_eq!(calls.take(), ["Pause music"]);
+    }
+
+    #[tokio::test]
+    async fn nothing_is_paused_without_playing_player() {
+        let bus = PrivateBus::start();
+        let calls = Calls::default();
+        let _idle = FakePlayer::start(&bus, "idle", PAUSED, &calls).await;
+
+        let mut mpris = Mpris::new(bus.connect().await);
+        mpris.pause().await.unwrap();
+        mpris.resume().await.unwrap();
+        assert!(calls.take().is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::identity::Identity;
+use crate::proximity::{Battery, PairedMessage};
+use btleplug::api::BDAddr;
+use chrono::{DateTime, Local};
+use std::time::{Duration, Instant};
+
+/* cards of devices which went away without closing the lid are forgotten */
+const CARD_EXPIRY: Duration = Duration::from_secs(5 * 60);
+
+/* identifies a device for the registry: the registered name or the public
+ * address of a device resolved by its IRK. The advertising address rotates
+ * and an identical model of someone else matches the registered device, so
+ * there is no key for other reports. */
+pub fn device_key(device: Option<&str>, identity: Option<&Identity>) -> Option<String> {
+    let identity = identity.filter(|identity| identity.resolved)?;
+    Some(device.map_or_else(|| identity.address.clone(), String::from))
+}
+
+/* tag of the notification, which falls back to the address of the report */
+pub fn device_tag(key: Option<&str>, address: &BDAddr) -> String {
+    format!("airpods-notify.{}", key.map_or_else(|| address.to_string(), String::from))
+}
+
+/* last report of a bud or the case */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Seen {
+    pub percent: u8,
+    pub charging: bool,
+    pub at: DateTime<Local>,
+}
+
+impl Seen {
+    fn from(battery: Battery, charging: bool) -> Option<Seen> {
+        match battery {
+            Battery::Level(level) => Some(Seen {
+                percent: level.min(10) * 10,
+                charging,
+                at: Local::now(),
+            }),
+            Battery::None => None,
+        }
+    }
+}
+
+/* notification of a single nearby device */
+pub struct Card<H> {
+    /* name of the registered device */
+    pub device: Option<String>,
+    /* bonded device of BlueZ, if the report was resolved */
+    pub identity: Option<Identity>,
+    pub msg: PairedMessage,
+    /* components keep their last report, even if it is missing in the current one */
+    pub left: Option<Seen>,
+    pub right: Option<Seen>,
+    pub case: Option<Seen>,
+    /* signal strength of the last report */
+    pub rssi: i16,
+    /* both buds advertise with their own, rotating address */
+    addresses: Vec<BDAddr>,
+    pub handle: H,
+    updated: Instant,
+}
+
+impl<H> Card<H> {
+    pub fn new(device: Option<String>, identity: Option<Identity>, address: BDAddr, rssi: i16, msg: PairedMessage, handle: H) -> Card<H> {
+        let mut card = Card {
+            device,
+            identity,
+            msg,
+            left: None,
+            right: None,
+            case: None,
+            rssi,
+            addresses: vec![address],
+            handle,
+            updated: Instant::now(),
+        };
+        card.record(&msg);
+        card
+    }
+
+    pub fn key(&self) -> Option<String> {
+        device_key(self.device.as_deref(), self.identity.as_ref())
+    }
+
+    pub fn tag(&self) -> String {
+        device_tag(self.key().as_deref(), &self.addresses[0])
+    }
+
+    fn record(&mut self, msg: &PairedMessage) {
+        let seen = [
+            (&mut self.left, msg.left_battery_level, msg.left_charging),
+            (&mut self.right, msg.right_battery_level, msg.right_charging),
+            (&mut self.case, msg.case_battery_level, msg.case_charging),
+        ];
+        for (component, battery, charging) in seen {
+            if let Some(seen) = Seen::from(battery, charging) {
+                *component = Some(seen);
+            }
+        }
+    }
+
+    fn is_same_device(&self, device: Option<&str>, address: &BDAddr, msg: &PairedMessage) -> bool {
+        match (self.device.as_deref(), device) {
+            (Some(own), Some(other)) => own == other,
+            (Some(_), None) | (None, Some(_)) => false,
+            // the message of the other bud has the opposite part
+            (None, None) => self.addresses.contains(address)
+                || self.msg.cmp(&PairedMessage { part: self.msg.part, ..*msg }),
+        }
+    }
+
+    pub fn update(&mut self, identity: Option<Identity>, address: BDAddr, rssi: i16, msg: PairedMessage) {
+        if !self.addresses.contains(&address) {
+            self.addresses.push(address);
+        }
+        // a later report may resolve a device, which was unknown before
+        if identity.is_some() {
+            self.identity = identity;
+        }
+        self.record(&msg);
+        self.msg = msg;
+        self.rssi = rssi;
+        self.updated = Instant::now();
+    }
+}
+
+/* One notification per nearby device, limited to a maximum number of cards */
+pub struct Cards<H> {
+    max: usize,
+    cards: Vec<Card<H>>,
+}
+
+impl<H> Cards<H> {
+    pub fn new(max: usize) -> Cards<H> {
+        Cards {
+            max: max.max(1),
+            cards: Vec::new(),
+        }
+    }
+
+    pub fn find(&mut self, device: Option<&str>, address: &BDAddr, msg: &PairedMessage) -> Option<&mut Card<H>> {
+        self.cards.iter_mut().find(|card| card.is_same_device(device, address, msg))
+    }
+
+    /* lookup by other properties, e.g. the id of the notification */
+    pub fn find_by(&mut self, predicate: impl Fn(&Card<H>) -> bool) -> Option<&mut Card<H>> {
+        self.cards.iter_mut().find(|card| predicate(card))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Card<H>> {
+        self.cards.iter_mut()
+    }
+
+    pub fn remove_by(&mut self, predicate: impl Fn(&Card<H>) -> bool) -> Option<Card<H>> {
+        let index = self.cards.iter().position(predicate)?;
+        Some(self.cards.remove(index))
+    }
+
+    pub fn remove(&mut self, device: Option<&str>, address: &BDAddr, msg: &PairedMessage) -> Option<Card<H>> {
+        self.remove_by(|card| card.is_same_device(device, address, msg))
+    }
+
+    /* returns the least recently updated cards which exceed the maximum */
+    pub fn insert(&mut self, card: Card<H>) -> Vec<Card<H>> {
+        self.cards.push(card);
+        self.cards.sort_by_key(|card| card.updated);
+        let excess = self.cards.len().saturating_sub(self.max);
+        self.cards.drain(..excess).collect()
+    }
+
+    pub fn expire(&mut self) -> Vec<Card<H>> {
+        let (expired, cards) = self.cards.drain(..)
+            .partition(|card| card.updated.elapsed() >= CARD_EXPIRY);
+        self.cards = cards;
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::paired_message;
+
+    #[test]
+    fn cards_expire_after_the_device_went_away() {
+        let mut cards = Cards::new(3);
+        let address = BDAddr::from([0x51, 0, 0, 0, 0, 1]);
+        cards.insert(Card::new(Some("Work".to_string()), None, address, -60, paired_message(5, 5, 5), 1));
+        cards.insert(Card::new(None, None, BDAddr::from([0x51, 0, 0, 0, 0, 2]), -60, paired_message(3, 3, 3), 2));
+        // the monotonic clock may have started too recently to go back that far
+        let Some(updated) = cards.cards[0].updated.checked_sub(CARD_EXPIRY) else {
+            return;
+        };
+        cards.cards[0].updated = updated;
+
+        let expired: Vec<u32> = cards.expire().into_iter().map(|card| card.handle).collect();
+        assert_eq!(expired, vec![1]);
+        assert!(cards.find(Some("Work"), &address, &paired_message(5, 5, 5)).is_none());
+        assert!(cards.expire().is_empty());
+    }
+
+    #[test]
+    fn only_resolved_devices_have_a_key() {
+        let identity = |resolved| Identity {
+            alias: "AirPods".to_string(),
+            address: "AA:BB:CC:DD:EE:01".to_string(),
+            connected: false,
+            resolved,
+        };
+        assert_eq!(device_key(Some("Work"), Some(&identity(true))), Some("Work".to_string()));
+        assert_eq!(device_key(None, Some(&identity(true))), Some("AA:BB:CC:DD:EE:01".to_string()));
+        // guessed by the model, which might be someone else's
+        assert_eq!(device_key(Some("Work"), Some(&identity(false))), None);
+        assert_eq!(device_key(Some("Work"), None), None);
+        assert_eq!(device_key(None, None), None);
+
+        let address = BDAddr::from([0x51, 0, 0, 0, 0, 1]);
+        assert_eq!(device_tag(None, &address), "airpods-notify.51:00:00:00:00:01");
+        assert_eq!(device_tag(Some("Work"), &address), "airpods-notify.Work");
+    }
+}
@@ -0,0 +1,292 @@
+use crate::config::{NotifierBackend, NotifierConfig};
+use crate::portal::Portal;
+use crate::server::Server;
+use notify_rust::{Hint, Notification, Urgency};
+#[cfg(test)]
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
+#[cfg(test)]
+use std::rc::Rc;
+use tokio::sync::mpsc;
+use zbus::Connection;
+
+/* id of a shown notification, assigned by the notifier */
+pub type Id = u32;
+
+/* reaction of the user on a notification */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    Action(String),
+    Closed,
+}
+
+/* Shows notifications on a backend. The responses of the user are sent
+ * through the channel which was given to the backend. */
+pub trait Notifier {
+    /* None if the notification was not shown or cannot be referred to */
+    fn show(&mut self, notification: &Notification) -> Option<Id>;
+    /* the id changes if the backend shows the update as a new notification */
+    fn update(&mut self, id: Id, notification: &Notification) -> Option<Id>;
+    fn close(&mut self, id: Id);
+    /* false if buttons are not shown or their responses are not received */
+    fn has_actions(&self) -> bool;
+    /* Called periodically, e.g. to look up a missing server again. True
+     * once the backend is available again, so notifications which went
+     * elsewhere meanwhile can be shown again. */
+    fn retry(&mut self) -> bool {
+        false
+    }
+}
+
+/* the notification server is used if the portal is not available */
+pub async fn from_config(config: &NotifierConfig, responses: mpsc::Sender<(Id, Response)>) -> Box<dyn Notifier> {
+    match config.backend {
+        NotifierBackend::Terminal => Box::new(Terminal::new(config)),
+        NotifierBackend::Log => Box::new(Log::default()),
+        backend => match Connection::session().await {
+            Ok(connection) => from_connection(connection, backend, Portal::is_sandboxed(), config, responses).await,
+            Err(err) => {
+                eprintln!("no session bus, notifications are written to the terminal: {}", err);
+                Box::new(Terminal::new(config))
+            }
+        },
+    }
+}
+
+/* Under auto the portal is tried first inside a sandbox. Elsewhere it is
+ * only used if there is no notification server, as the portal does not
+ * report closed notifications. */
+async fn from_connection(connection: Connection, backend: NotifierBackend, sandboxed: bool, config: &NotifierConfig,
+    responses: mpsc::Sender<(Id, Response)>) -> Box<dyn Notifier> {
+    let portal_first = backend == NotifierBackend::Portal || backend == NotifierBackend::Auto && sandboxed;
+    if portal_first {
+        match Portal::new(&connection, responses.clone()).await {
+            Ok(portal) => return Box::new(portal),
+            Err(err) => eprintln!("notification portal is not available: {}", err),
+        }
+    }
+    let server = Server::new(connection.clone(), responses.clone(), Terminal::new(config)).await;
+    if backend != NotifierBackend::Auto || portal_first || server.is_available() {
+        return Box::new(server);
+    }
+    match Portal::new(&connection, responses).await {
+        Ok(portal) => Box::new(portal),
+        Err(_) => Box::new(server),
+    }
+}
+
+/* single line of a notification */
+fn line(notification: &Notification) -> String {
+    format!("{}: {}", notification.summary, notification.body.trim_end().replace('\n', ", "))
+}
+
+fn is_critical(notification: &Notification) -> bool {
+    notification.hints.contains(&Hint::Urgency(Urgency::Critical))
+}
+
+/* Writes notifications to the terminal, which also works over SSH. The
+ * terminal is asked to show a desktop notification with OSC 9 (iTerm2,
+ * Windows Terminal, kitty) or OSC 777 (urxvt, foot, WezTerm) and critical
+ * ones ring the bell. Updates are printed only if their text changed. */
+pub struct Terminal {
+    osc: u16,
+    bell: bool,
+    next: Id,
+    lines: HashMap<Id, String>,
+}
+
+impl Terminal {
+    pub fn new(config: &NotifierConfig) -> Terminal {
+        Terminal {
+            osc: config.osc,
+            bell: config.bell,
+            next: 0,
+            lines: HashMap::new(),
+        }
+    }
+
+    fn write(&self, notification: &Notification, popup: bool) {
+        let mut stdout = std::io::stdout();
+        // escape sequences would end up in redirected output
+        if popup && stdout.is_terminal() {
+            let summary = notification.summary.replace(['\x07', '\x1b', ';'], " ");
+            let body = notification.body.trim_end().replace(['\x07', '\x1b', ';'], " ").replace('\n', ", ");
+            let _ = match self.osc {
+                9 => write!(stdout, "\x1b]9;{}: {}\x07", summary, body),
+                777 => write!(stdout, "\x1b]777;notify;{};{}\x07", summary, body),
+                _ => Ok(()),
+            };
+            if self.bell && is_critical(notification) {
+                let _ = write!(stdout, "\x07");
+            }
+        }
+        let _ = writeln!(stdout, "{}", line(notification));
+    }
+}
+
+impl Notifier for Terminal {
+    fn show(&mut self, notification: &Notification) -> Option<Id> {
+        self.write(notification, true);
+        self.next += 1;
+        self.lines.insert(self.next, line(notification));
+        Some(self.next)
+    }
+
+    fn update(&mut self, id: Id, notification: &Notification) -> Option<Id> {
+        let line = line(notification);
+        if self.lines.get(&id) != Some(&line) {
+            self.write(notification, false);
+            self.lines.insert(id, line);
+        }
+        Some(id)
+    }
+
+    fn close(&mut self, id: Id) {
+        self.lines.remove(&id);
+    }
+
+    fn has_actions(&self) -> bool {
+        false
+    }
+}
+
+/* one plain line per notification on stderr, e.g. for the journal */
+#[derive(Default)]
+pub struct Log {
+    next: Id,
+    lines: HashMap<Id, String>,
+}
+
+impl Notifier for Log {
+    fn show(&mut self, notification: &Notification) -> Option<Id> {
+        self.next += 1;
+        let line = line(notification);
+        eprintln!("notification {}: {}", self.next, line);
+        self.lines.insert(self.next, line);
+        Some(self.next)
+    }
+
+    fn update(&mut self, id: Id, notification: &Notification) -> Option<Id> {
+        let line = line(notification);
+        if self.lines.get(&id) != Some(&line) {
+            eprintln!("notification {} updated: {}", id, line);
+            self.lines.insert(id, line);
+        }
+        Some(id)
+    }
+
+    fn close(&mut self, id: Id) {
+        if self.lines.remove(&id).is_some() {
+            eprintln!("notification {} closed", id);
+        }
+    }
+
+    fn has_actions(&self) -> bool {
+        false
+    }
+}
+
+/* what happened to a notification */
+#[cfg(test)]
+#[derive(Debug, Clone)]
+pub enum Record {
+    Shown(Id, Notification),
+    Updated(Id, Notification),
+    Closed(Id),
+}
+
+/* Keeps the notifications in memory, e.g. to check which notifications
+ * nearby mode produced for replayed advertisements. Clones share the
+ * records, so they can be inspected after the notifier was handed over. */
+#[cfg(test)]
+#[derive(Clone)]
+pub struct Recorder {
+    records: Rc<RefCell<Vec<Record>>>,
+    next: Rc<Cell<Id>>,
+    responses: mpsc::Sender<(Id, Response)>,
+}
+
+#[cfg(test)]
+impl Recorder {
+    pub fn new(responses: mpsc::Sender<(Id, Response)>) -> Recorder {
+        Recorder {
+            records: Rc::default(),
+            next: Rc::default(),
+            responses,
+        }
+    }
+
+    pub fn records(&self) -> Vec<Record> {
+        self.records.borrow().clone()
+    }
+
+    /* simulates a click on a button of the notification */
+    pub async fn click(&self, id: Id, action: &str) {
+        let _ = self.responses.send((id, Response::Action(action.to_string()))).await;
+    }
+}
+
+#[cfg(test)]
+impl Notifier for Recorder {
+    fn show(&mut self, notification: &Notification) -> Option<Id> {
+        let id = self.next.get() + 1;
+        self.next.set(id);
+        self.records.borrow_mut().push(Record::Shown(id, notification.clone()));
+        Some(id)
+    }
+
+    fn update(&mut self, id: Id, notification: &Notification) -> Option<Id> {
+        self.records.borrow_mut().push(Record::Updated(id, notification.clone()));
+        Some(id)
+    }
+
+    fn close(&mut self, id: Id) {
+        self.records.borrow_mut().push(Record::Closed(id));
+    }
+
+    fn has_actions(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FakeNotifications, FakePortal, PrivateBus};
+
+    async fn notifier(bus: &PrivateBus, backend: NotifierBackend, sandboxed: bool) -> Box<dyn Notifier> {
+        let (responses, _) = mpsc::channel(8);
+        let mut notifier = from_connection(bus.connect().await, backend, sandboxed, &NotifierConfig::default(), responses).await;
+        let mut notification = Notification::new();
+        notification.summary("AirPods");
+        notifier.show(&notification);
+        notifier
+    }
+
+    #[tokio::test]
+    async fn auto_prefers_the_server_outside_a_sandbox() {
+        let bus = PrivateBus::start();
+        let server = FakeNotifications::start(&bus, &["body"]).await;
+        let portal = FakePortal::start(&bus).await;
+        let _notifier = notifier(&bus, NotifierBackend::Auto, false).await;
+        assert_eq!(server.calls.wait(1).await, ["Notify 1 AirPods [] []"]);
+
+        let _notifier = notifier(&bus, NotifierBackend::Auto, true).await;
+        assert_eq!(portal.calls.wait(1).await, ["Add 1 title=AirPods body="]);
+        assert!(server.calls.take().is_empty());
+    }
+
+    #[tokio::test]
+    async fn auto_falls_back_to_the_portal() {
+        let bus = PrivateBus::start();
+        let portal = FakePortal::start(&bus).await;
+        let _notifier = notifier(&bus, NotifierBackend::Auto, false).await;
+        assert_eq!(portal.calls.wait(1).await, ["Add 1 title=AirPods body="]);
+
+        // the server keeps looking for a notification server
+        let _notifier = notifier(&bus, NotifierBackend::DBus, false).await;
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(portal.calls.take().is_empty());
+    }
+}
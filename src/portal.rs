@@ -0,0 +1,242 @@
+use crate::notifier::{Id, Notifier, Response};
+use futures::StreamExt;
+use image::{DynamicImage, ImageFormat, RgbImage, RgbaImage};
+use notify_rust::{Hint, Notification, Urgency};
+use std::collections::HashMap;
+use std::env;
+use std::io::Cursor;
+use std::path::Path;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use zbus::zvariant::{OwnedValue, Value};
+use zbus::{message, proxy, Connection, MatchRule, MessageStream};
+
+const INTERFACE: &str = "org.freedesktop.portal.Notification";
+const PATH: &str = "/org/freedesktop/portal/desktop";
+/* action of a click on the notification itself */
+const DEFAULT_ACTION: &str = "default";
+
+#[proxy(
+    interface = "org.freedesktop.portal.Notification",
+    default_service = "org.freedesktop.portal.Desktop",
+    default_path = "/org/freedesktop/portal/desktop"
+)]
+trait PortalNotification {
+    fn add_notification(&self, id: &str, notification: HashMap<&str, Value<'_>>) -> zbus::Result<()>;
+
+    fn remove_notification(&self, id: &str) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn version(&self) -> zbus::Result<u32>;
+}
+
+/* raw image of the hint as PNG, which is what the portal accepts */
+fn png(hint: &Hint) -> Option<Vec<u8>> {
+    let (_, value) = <(&str, Value)>::from(hint);
+    let (width, height, _, alpha, _, _, data) =
+        <(i32, i32, i32, bool, i32, i32, Vec<u8>)>::try_from(OwnedValue::try_from(value).ok()?).ok()?;
+    let (width, height) = (u32::try_from(width).ok()?, u32::try_from(height).ok()?);
+    let image = match alpha {
+        true => DynamicImage::ImageRgba8(RgbaImage::from_raw(width, height, data)?),
+        false => DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, data)?),
+    };
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageFormat::Png).ok()?;
+    Some(png.into_inner())
+}
+
+/* the notification as dictionary of AddNotification */
+fn to_portal(notification: &Notification) -> HashMap<&'static str, Value<'static>> {
+    let mut portal = HashMap::new();
+    portal.insert("title", Value::from(notification.summary.clone()));
+    portal.insert("body", Value::from(notification.body.trim_end().to_string()));
+
+    let priority = notification.hints.iter().find_map(|hint| match hint {
+        Hint::Urgency(Urgency::Low) => Some("low"),
+        Hint::Urgency(Urgency::Normal) => Some("normal"),
+        Hint::Urgency(Urgency::Critical) => Some("urgent"),
+        _ => None,
+    });
+    if let Some(priority) = priority {
+        portal.insert("priority", Value::from(priority));
+    }
+
+    // icons are serialized GIcons
+    let image = notification.hints.iter()
+        .find(|hint| matches!(hint, Hint::ImageData(_)))
+        .and_then(png);
+    if let Some(image) = image {
+        portal.insert("icon", Value::from(("bytes", Value::from(image))));
+    } else if !notification.icon.is_empty() {
+        portal.insert("icon", Value::from(("themed", Value::from(vec![notification.icon.clone()]))));
+    }
+
+    // the actions are pairs of key and label
+    let mut buttons = Vec::new();
+    for action in notification.actions.chunks_exact(2) {
+        if action[0] == DEFAULT_ACTION {
+            portal.insert("default-action", Value::from(DEFAULT_ACTION));
+        } else {
+            buttons.push(HashMap::from([
+                ("label", Value::from(action[1].clone())),
+                ("action", Value::from(action[0].clone())),
+            ]));
+        }
+    }
+    if !buttons.is_empty() {
+        portal.insert("buttons", Value::from(buttons));
+    }
+    portal
+}
+
+/* calls of the notifier, which are made in this order by the portal task */
+enum Request {
+    Add(Id, Box<Notification>),
+    Remove(Id),
+}
+
+/* Notifier of the org.freedesktop.portal.Notification portal, which is
+ * reachable from a sandbox. The connection is injected, so the notifier
+ * works the same way with the session bus or a private bus. The ids are
+ * chosen by the notifier, so the calls are queued to a task and a slow
+ * portal does not hold up the daemon. */
+pub struct Portal {
+    requests: mpsc::UnboundedSender<Request>,
+    next: Id,
+    listener: JoinHandle<()>,
+}
+
+impl Portal {
+    /* fails if the portal does not provide notifications */
+    pub async fn new(connection: &Connection, responses: mpsc::Sender<(Id, Response)>) -> zbus::Result<Portal> {
+        let portal = PortalNotificationProxy::new(connection).await?;
+        let version = portal.version().await?;
+        eprintln!("notification portal: version {}", version);
+        let (requests, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(send(portal, receiver));
+        Ok(Portal {
+            requests,
+            next: 0,
+            listener: tokio::spawn(listen(connection.clone(), responses)),
+        })
+    }
+
+    /* Flatpak or Snap, where the notification server may not be reachable */
+    pub fn is_sandboxed() -> bool {
+        Path::new("/.flatpak-info").exists() || env::var_os("SNAP").is_some()
+    }
+
+    fn add(&self, id: Id, notification: &Notification) -> Option<Id> {
+        // the task ends only with the notifier
+        let _ = self.requests.send(Request::Add(id, Box::new(notification.clone())));
+        Some(id)
+    }
+}
+
+impl Drop for Portal {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+impl Notifier for Portal {
+    fn show(&mut self, notification: &Notification) -> Option<Id> {
+        self.next += 1;
+        self.add(self.next, notification)
+    }
+
+    /* a notification with the same id is replaced */
+    fn update(&mut self, id: Id, notification: &Notification) -> Option<Id> {
+        self.add(id, notification)
+    }
+
+    fn close(&mut self, id: Id) {
+        let _ = self.requests.send(Request::Remove(id));
+    }
+
+    fn has_actions(&self) -> bool {
+        true
+    }
+}
+
+/* makes the calls of the notifier until it is dropped */
+async fn send(portal: PortalNotificationProxy<'static>, mut requests: mpsc::UnboundedReceiver<Request>) {
+    while let Some(request) = requests.recv().await {
+        let result = match request {
+            Request::Add(id, notification) => portal.add_notification(&id.to_string(), to_portal(&notification)).await,
+            Request::Remove(id) => portal.remove_notification(&id.to_string()).await,
+        };
+        if let Err(err) = result {
+            eprintln!("notification failed: {}", err);
+        }
+    }
+}
+
+/* forwards the clicked buttons, the portal does not report closed notifications */
+async fn listen(connection: zbus::Connection, responses: mpsc::Sender<(Id, Response)>) {
+    let rule = MatchRule::builder()
+        .msg_type(message::Type::Signal)
+        .interface(INTERFACE)
+        .and_then(|rule| rule.path(PATH))
+        .and_then(|rule| rule.member("ActionInvoked"))
+        .map(|rule| rule.build());
+    let signals = match rule {
+        Ok(rule) => MessageStream::for_match_rule(rule, &connection, None).await,
+        Err(err) => Err(err),
+    };
+    let mut signals = match signals {
+        Ok(signals) => signals,
+        Err(err) => {
+            eprintln!("notification responses are not available: {}", err);
+            return;
+        }
+    };
+    while let Some(Ok(signal)) = signals.next().await {
+        // the parameter of the action is not used
+        let response = signal.body().deserialize::<(String, String, Vec<OwnedValue>)>().ok()
+            .and_then(|(id, action, _)| Some((id.parse().ok()?, Response::Action(action))));
+        if let Some(response) = response {
+            if responses.send(response).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FakePortal, PrivateBus};
+
+    #[tokio::test]
+    async fn notifications_are_added_to_the_portal() {
+        let bus = PrivateBus::start();
+        let fake = FakePortal::start(&bus).await;
+        let (responses, mut received) = mpsc::channel(8);
+        let mut portal = Portal::new(&bus.connect().await, responses).await.unwrap();
+
+        let mut notification = Notification::new();
+        notification.summary("AirPods Pro");
+        notification.body("Left 50%\n");
+        notification.icon("audio-headphones");
+        notification.hint(Hint::Urgency(Urgency::Critical));
+        notification.action("default", "default");
+        notification.action("card-details", "Details");
+        let id = portal.show(&notification).unwrap();
+        assert_eq!(portal.update(id, &notification), Some(id));
+        portal.close(id);
+        let added = "Add 1 title=AirPods Pro body=Left 50% priority=urgent default-action=default \
+            icon=themed:audio-headphones buttons=card-details:Details";
+        assert_eq!(fake.calls.wait(3).await, [added, added, "Remove 1"]);
+
+        fake.click("1", "card-details").await;
+        assert_eq!(received.recv().await, Some((1, Response::Action("card-details".to_string()))));
+    }
+
+    #[tokio::test]
+    async fn missing_portal_is_reported() {
+        let bus = PrivateBus::start();
+        let (responses, _) = mpsc::channel(8);
+        assert!(Portal::new(&bus.connect().await, responses).await.is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use crate::config::LockConfig;
+use crate::identity::Irk;
+use crate::locate::Locator;
+use crate::proximity::{PairedMessage, Lid, Plugged};
+use btleplug::api::BDAddr;
+use std::time::Instant;
+
+/* Locks the session when the headset of the owner leaves. Only reports
+ * resolved with its IRK count, so other headsets of the same model
+ * cannot keep the session unlocked. */
+pub struct PresenceLock {
+    config: LockConfig,
+    irk: Irk,
+    locator: Locator,
+    last_seen: Option<Instant>,
+    /* both buds are in the closed case */
+    stored: bool,
+    /* the smoothed signal is below the departure level since this time */
+    departed_since: Option<Instant>,
+    /* the headset has to be seen close again after a lock */
+    armed: bool,
+}
+
+impl PresenceLock {
+    pub fn new(config: LockConfig, irk: Irk) -> PresenceLock {
+        PresenceLock {
+            config,
+            irk,
+            locator: Locator::default(),
+            last_seen: None,
+            stored: false,
+            departed_since: None,
+            armed: false,
+        }
+    }
+
+    pub fn update(&mut self, address: &BDAddr, msg: &PairedMessage, rssi: i16) {
+        if !self.irk.resolves(address) {
+            return;
+        }
+        let now = Instant::now();
+        self.last_seen = Some(now);
+        self.stored = matches!(msg.lid, Lid::Closed(_)) && msg.plugged_in_case == Plugged::Both;
+        if self.locator.update(rssi) < f64::from(self.config.departure_rssi) {
+            self.departed_since.get_or_insert(now);
+        } else {
+            self.departed_since = None;
+            self.armed = true;
+        }
+    }
+
+    /* true once per departure, if the session should be locked */
+    pub fn check(&mut self) -> bool {
+        if !self.armed || (self.stored && self.config.ignore_in_case) {
+            return false;
+        }
+        let absent = self.last_seen.is_some_and(|at| at.elapsed() >= self.config.absence());
+        let departed = self.departed_since.is_some_and(|since| since.elapsed() >= self.config.grace());
+        if absent || departed {
+            self.armed = false;
+            self.locator = Locator::default();
+        }
+        absent || departed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logind::Logind;
+    use crate::testing::{paired_message, FakeLogind, PrivateBus};
+
+    const IRK: &str = "00112233445566778899aabbccddeeff";
+
+    fn lock(config: LockConfig) -> (PresenceLock, Irk) {
+        let irk = Irk::from_hex(IRK).unwrap();
+        (PresenceLock::new(config, irk), irk)
+    }
+
+    #[tokio::test]
+    async fn absent_headset_locks_the_session_once() {
+        let bus = PrivateBus::start();
+        let fake = FakeLogind::start(&bus).await;
+        let logind = Logind::new(&bus.connect().await).await.unwrap();
+        let (mut presence, irk) = lock(LockConfig { absence_seconds: 0, ..LockConfig::default() });
+
+        // not before the headset was seen close
+        assert!(!presence.check());
+        presence.update(&irk.private_address([1, 2, 3]), &paired_message(5, 5, 5), -50);
+        for _ in 0..2 {
+            if presence.check() {
+                logind.lock().await.unwrap();
+            }
+        }
+        assert_eq!(fake.calls.take(), ["Lock"]);
+        assert!(logind.is_locked().await.unwrap());
+    }
+
+    #[test]
+    fn other_headsets_do_not_count() {
+        let (mut presence, _) = lock(LockConfig { absence_seconds: 0, ..LockConfig::default() });
+        let other = Irk::from_hex("ffeeddccbbaa99887766554433221100").unwrap();
+        presence.update(&other.private_address([1, 2, 3]), &paired_message(5, 5, 5), -50);
+        assert!(!presence.check());
+    }
+
+    #[test]
+    fn departure_needs_the_grace_period() {
+        let (mut presence, irk) = lock(LockConfig { grace_seconds: 3600, ..LockConfig::default() });
+        let address = irk.private_address([1, 2, 3]);
+        presence.update(&address, &paired_message(5, 5, 5), -50);
+        for _ in 0..20 {
+            presence.update(&address, &paired_message(5, 5, 5), -100);
+        }
+        assert!(presence.departed_since.is_some());
+        assert!(!presence.check());
+    }
+
+    #[test]
+    fn headset_in_the_closed_case_is_ignored() {
+        let (mut presence, irk) = lock(LockConfig { absence_seconds: 0, ..LockConfig::default() });
+        let stored = PairedMessage {
+            lid: Lid::Closed(1),
+            plugged_in_ear: Plugged::None,
+            plugged_in_case: Plugged::Both,
+            ..paired_message(5, 5, 5)
+        };
+        presence.update(&irk.private_address([1, 2, 3]), &stored, -50);
+        assert!(!presence.check());
+    }
+}
@@ -1,5 +1,6 @@
 use std::{collections::HashMap};
 use std::fmt;
+use serde::Deserialize;
 
 const AIRPODS_MANUFACTURER: u16 = 76;
 const PROXIMITIY_PROTOCOL: u8 = 0x7;
@@ -9,7 +10,7 @@ const PROXIMITIY_PAIRING_PROTOCOL_LENGTH: u8 = 15;
 const PROXIMITIY_PAIRED_PROTOCOL_LENGTH: u8 = 25;
 
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Part {
     LeftEarPlug,
     RightEarPlug,
@@ -87,7 +88,7 @@ impl fmt::Display for Lid {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
 pub enum Model {
     AirPods1,
     AirPods2,
@@ -128,16 +129,20 @@ impl Model {
         }
     }    
 
-    fn is_single_device(&self) -> bool {
-        match self {
-            Model::AirPodsMax => true,
-            Model::BeatsFlex => true,
-            Model::BeatsSolo3 => true,
-            Model::BeatsX => true,
-            Model::BeatsStudio3 => true,
-            Model::PowerbeatsPro => true,
-            _ => false,
-        }
+    /* product id of the Device ID profile, e.g. 0x200e for AirPodsPro */
+    pub fn from_product_id(id: u16) -> Model {
+        Model::from_bytes(id.to_le_bytes())
+    }
+
+    pub fn is_single_device(&self) -> bool {
+        matches!(self,
+            Model::AirPodsMax |
+            Model::BeatsFlex |
+            Model::BeatsSolo3 |
+            Model::BeatsX |
+            Model::BeatsStudio3 |
+            Model::PowerbeatsPro
+        )
     }
 }
 
@@ -147,7 +152,7 @@ impl fmt::Display for Model {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
 pub enum Color {
     White,
     Black,
@@ -214,7 +219,7 @@ pub struct PairedMessage {
 
 impl PairedMessage {
 
-    fn from_bytes(bytes: &Vec<u8>) -> PairedMessage {
+    fn from_bytes(bytes: &[u8]) -> PairedMessage {
         // ensure correct protocol (0x7)
         let device_model = Model::from_bytes(bytes[3..5].try_into().unwrap());
         let device_color = Color::from_u8(bytes[9]);
@@ -310,13 +315,20 @@ impl PairedMessage {
 }
 
 pub struct PairingMessage {
-    device_model: Model,
-    device_color: Color,
-    address: [u8; 6],
+    pub device_model: Model,
+    pub device_color: Color,
+    pub address: [u8; 6],
 }
 
 impl PairingMessage {
-    fn from_bytes(bytes: &Vec<u8>) -> PairingMessage {
+    pub fn address(&self) -> String {
+        self.address.iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<String>>()
+            .join(":")
+    }
+
+    fn from_bytes(bytes: &[u8]) -> PairingMessage {
         let device_model = Model::from_bytes(bytes[3..5].try_into().unwrap());
         let address = bytes[5..11].try_into().unwrap();
         let device_color = Color::from_u8(bytes[16]);
@@ -0,0 +1,147 @@
+use crate::config::Config;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+const REGISTRY_FILE: &str = "registry.toml";
+
+/* Devices silenced by the actions of their nearby cards, keyed by the
+ * registered name or the address of the device. The registry is kept in
+ * the state directory, so the configuration is never rewritten. */
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Registry {
+    /* muted until the end of this day */
+    muted: BTreeMap<String, NaiveDate>,
+    /* never shown again */
+    hidden: BTreeSet<String>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Registry {
+    /* default location is $XDG_STATE_HOME/airpods-notify/registry.toml */
+    pub fn load() -> Registry {
+        Registry::open(Config::state_dir().map(|dir| dir.join(REGISTRY_FILE)))
+    }
+
+    /* without a path, nothing is kept */
+    pub fn open(path: Option<PathBuf>) -> Registry {
+        let registry = match &path {
+            Some(path) if path.exists() => fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|content| toml::from_str(&content).map_err(|err| err.to_string()))
+                .unwrap_or_else(|err| {
+                    eprintln!("{}: {}", path.display(), err);
+                    Registry::default()
+                }),
+            _ => Registry::default(),
+        };
+        Registry { path, ..registry }
+    }
+
+    pub fn is_silenced(&self, key: &str, today: NaiveDate) -> bool {
+        self.hidden.contains(key) || self.is_muted(key, today)
+    }
+
+    /* muted for today, unlike hidden devices */
+    pub fn is_muted(&self, key: &str, today: NaiveDate) -> bool {
+        self.muted.get(key).is_some_and(|until| today <= *until)
+    }
+
+    pub fn mute(&mut self, key: &str, today: NaiveDate) -> Result<(), Box<dyn Error>> {
+        // expired entries are dropped on the way
+        self.muted.retain(|_, until| today <= *until);
+        self.muted.insert(key.to_string(), today);
+        self.save()
+    }
+
+    pub fn unmute(&mut self, key: &str) -> Result<(), Box<dyn Error>> {
+        self.muted.remove(key);
+        self.save()
+    }
+
+    pub fn hide(&mut self, key: &str) -> Result<(), Box<dyn Error>> {
+        self.hidden.insert(key.to_string());
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        let path = self.path.as_ref().ok_or("no state directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, toml::to_string(self)?)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    /* a file of the test below the temporary directory, removed when dropped */
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            TempFile(std::env::temp_dir().join(format!("airpods-notify-{}-{}", process::id(), name)).join(REGISTRY_FILE))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            if let Some(dir) = self.0.parent() {
+                let _ = fs::remove_dir_all(dir);
+            }
+        }
+    }
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    #[test]
+    fn muted_and_hidden_devices_are_kept() {
+        let file = TempFile::new("kept");
+        let mut registry = Registry::open(Some(file.0.clone()));
+        registry.mute("Work", day(1)).unwrap();
+        registry.hide("AA:BB:CC:DD:EE:02").unwrap();
+
+        let registry = Registry::open(Some(file.0.clone()));
+        assert!(registry.is_muted("Work", day(1)));
+        assert!(registry.is_silenced("AA:BB:CC:DD:EE:02", day(1)));
+        assert!(!registry.is_silenced("Home", day(1)));
+        // muted for the day only
+        assert!(!registry.is_silenced("Work", day(2)));
+        assert!(registry.is_silenced("AA:BB:CC:DD:EE:02", day(2)));
+    }
+
+    #[test]
+    fn unmute_and_expired_entries_are_removed() {
+        let file = TempFile::new("removed");
+        let mut registry = Registry::open(Some(file.0.clone()));
+        registry.mute("Work", day(1)).unwrap();
+        registry.mute("Home", day(2)).unwrap();
+        registry.unmute("Home").unwrap();
+        let content = fs::read_to_string(&file.0).unwrap();
+        assert!(!content.contains("Work") && !content.contains("Home"), "{}", content);
+    }
+
+    #[test]
+    fn broken_or_missing_file_starts_empty() {
+        let file = TempFile::new("broken");
+        fs::create_dir_all(file.0.parent().unwrap()).unwrap();
+        fs::write(&file.0, "muted = 1").unwrap();
+        assert!(!Registry::open(Some(file.0.clone())).is_silenced("Work", day(1)));
+
+        let mut registry = Registry::open(None);
+        assert!(registry.mute("Work", day(1)).is_err());
+        assert!(registry.is_muted("Work", day(1)));
+    }
+}
@@ -0,0 +1,71 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, Weekday};
+use serde::Deserialize;
+
+/* e.g. weekdays from 22:00 until 07:00 of the next day */
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Period {
+    /* days on which the period starts, all days if empty */
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl Period {
+    fn starts_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    pub fn contains(&self, now: &DateTime<Local>) -> bool {
+        let time = now.time();
+        let today = now.weekday();
+        if self.start <= self.end {
+            self.starts_on(today) && time >= self.start && time < self.end
+        } else {
+            // the period runs over midnight
+            let yesterday = (now.date_naive() - Duration::days(1)).weekday();
+            (self.starts_on(today) && time >= self.start) || (self.starts_on(yesterday) && time < self.end)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn period(config: &str) -> Period {
+        toml::from_str(config).unwrap()
+    }
+
+    /* 2024-01-01 is a Monday */
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 1, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn same_day_period_excludes_its_end() {
+        let lunch = period("start = \"12:00\"\nend = \"13:00\"");
+        assert!(!lunch.contains(&at(1, 11, 59)));
+        assert!(lunch.contains(&at(1, 12, 0)));
+        assert!(lunch.contains(&at(3, 12, 59)));
+        assert!(!lunch.contains(&at(1, 13, 0)));
+    }
+
+    #[test]
+    fn night_belongs_to_the_day_it_starts() {
+        let weekdays = period("days = [\"Mon\", \"Tue\", \"Wed\", \"Thu\", \"Fri\"]\nstart = \"22:00\"\nend = \"07:00\"");
+        // Monday night until Tuesday morning
+        assert!(!weekdays.contains(&at(1, 21, 59)));
+        assert!(weekdays.contains(&at(1, 22, 0)));
+        assert!(weekdays.contains(&at(2, 6, 59)));
+        assert!(!weekdays.contains(&at(2, 7, 0)));
+        // the morning after Friday night is Saturday, but not the one after
+        assert!(weekdays.contains(&at(6, 6, 0)));
+        assert!(!weekdays.contains(&at(6, 22, 0)));
+        assert!(!weekdays.contains(&at(7, 6, 0)));
+        // Monday morning follows Sunday night
+        assert!(!weekdays.contains(&at(8, 6, 0)));
+    }
+}
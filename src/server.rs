@@ -0,0 +1,414 @@
+use crate::notifier::{Id, Notifier, Response, Terminal};
+use futures::StreamExt;
+use notify_rust::{Hint, Notification};
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use zbus::{message, proxy, Connection, MatchRule, MessageStream};
+use zbus::zvariant::Value;
+
+/* a missing notification server is looked up again after this time */
+const RETRY: Duration = Duration::from_secs(30);
+const INTERFACE: &str = "org.freedesktop.Notifications";
+const PATH: &str = "/org/freedesktop/Notifications";
+
+#[proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[String],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+
+    fn close_notification(&self, id: u32) -> zbus::Result<()>;
+
+    fn get_capabilities(&self) -> zbus::Result<Vec<String>>;
+
+    /* name, vendor, version and specification version */
+    fn get_server_information(&self) -> zbus::Result<(String, String, String, String)>;
+}
+
+/* features of the notification server, see GetCapabilities */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    pub actions: bool,
+    pub images: bool,
+    pub persistence: bool,
+}
+
+impl Capabilities {
+    fn from(capabilities: &[String]) -> Capabilities {
+        let has = |name: &str| capabilities.iter().any(|capability| capability == name);
+        Capabilities {
+            actions: has("actions"),
+            images: has("icon-static") || has("icon-multi"),
+            persistence: has("persistence"),
+        }
+    }
+}
+
+/* calls of the notifier, which are made in this order by the client task */
+enum Request {
+    Notify { id: Id, update: bool, notification: Box<Notification> },
+    Close(Id),
+    Connect,
+}
+
+/* state of the server as last seen by the client task */
+#[derive(Default)]
+struct Status {
+    /* only known while the server is available */
+    capabilities: Option<Capabilities>,
+    /* the server was found again after it went missing */
+    reconnected: bool,
+}
+
+/* Notifier of the org.freedesktop.Notifications server, which adapts the
+ * notifications to the features of the running server. The calls are
+ * queued to a task which owns the connection, so a slow or missing
+ * server never holds up the daemon. The notifier hands out its own ids,
+ * which stay the same when the server replaces a notification. All
+ * notifications are shown through a single connection, so the signals of
+ * their buttons are received even from servers which send them only to
+ * the sender. Without a server, notifications go to the terminal and the
+ * server is looked up again later. */
+pub struct Server {
+    requests: mpsc::UnboundedSender<Request>,
+    status: Arc<Mutex<Status>>,
+    next: Id,
+    checked: Instant,
+}
+
+impl Server {
+    pub async fn new(connection: Connection, responses: mpsc::Sender<(Id, Response)>, fallback: Terminal) -> Server {
+        let status = Arc::new(Mutex::new(Status::default()));
+        let mut client = Client {
+            connection,
+            notifications: None,
+            signals: None,
+            ids: HashMap::new(),
+            status: status.clone(),
+            responses,
+            fallback,
+        };
+        if let Err(err) = client.connect().await {
+            eprintln!("no notification server, notifications are written to the terminal: {}", err);
+        }
+        let (requests, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(client.run(receiver));
+        Server {
+            requests,
+            status,
+            next: 0,
+            checked: Instant::now(),
+        }
+    }
+
+    /* false while notifications go to the terminal */
+    pub fn is_available(&self) -> bool {
+        self.capabilities().is_some()
+    }
+
+    fn capabilities(&self) -> Option<Capabilities> {
+        self.status.lock().unwrap().capabilities.clone()
+    }
+
+    fn send(&self, request: Request) {
+        // the task only ends with the runtime
+        let _ = self.requests.send(request);
+    }
+}
+
+impl Notifier for Server {
+    /* the id is missing if the notification went to the terminal */
+    fn show(&mut self, notification: &Notification) -> Option<Id> {
+        let available = self.is_available();
+        self.next += 1;
+        self.send(Request::Notify { id: self.next, update: false, notification: Box::new(notification.clone()) });
+        available.then_some(self.next)
+    }
+
+    fn update(&mut self, id: Id, notification: &Notification) -> Option<Id> {
+        self.capabilities()?;
+        self.send(Request::Notify { id, update: true, notification: Box::new(notification.clone()) });
+        Some(id)
+    }
+
+    fn close(&mut self, id: Id) {
+        self.send(Request::Close(id));
+    }
+
+    fn has_actions(&self) -> bool {
+        self.capabilities().is_some_and(|capabilities| capabilities.actions)
+    }
+
+    fn retry(&mut self) -> bool {
+        let mut status = self.status.lock().unwrap();
+        if status.capabilities.is_none() && self.checked.elapsed() >= RETRY {
+            self.checked = Instant::now();
+            let _ = self.requests.send(Request::Connect);
+        }
+        mem::take(&mut status.reconnected)
+    }
+}
+
+/* Owns the proxy of the server and maps the ids of the notifier to those
+ * of the server. */
+struct Client {
+    connection: Connection,
+    notifications: Option<NotificationsProxy<'static>>,
+    signals: Option<MessageStream>,
+    /* id of the notifier -> id of the server */
+    ids: HashMap<Id, u32>,
+    status: Arc<Mutex<Status>>,
+    responses: mpsc::Sender<(Id, Response)>,
+    fallback: Terminal,
+}
+
+impl Client {
+    async fn run(mut self, mut requests: mpsc::UnboundedReceiver<Request>) {
+        loop {
+            let signal = async {
+                match &mut self.signals {
+                    Some(signals) => signals.next().await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                request = requests.recv() => match request {
+                    Some(Request::Notify { id, update, notification }) => self.notify(id, update, &notification).await,
+                    Some(Request::Close(id)) => self.close(id).await,
+                    Some(Request::Connect) => {
+                        if self.connect().await.is_ok() {
+                            self.status.lock().unwrap().reconnected = true;
+                        }
+                    }
+                    None => break,
+                },
+                signal = signal => match signal {
+                    Some(Ok(signal)) => self.respond(&signal).await,
+                    // the bus connection itself is gone
+                    _ => self.signals = None,
+                },
+            }
+        }
+    }
+
+    async fn connect(&mut self) -> zbus::Result<()> {
+        let notifications = NotificationsProxy::new(&self.connection).await?;
+        let (name, _, version, _) = notifications.get_server_information().await?;
+        let capabilities = notifications.get_capabilities().await?;
+        eprintln!("notification server: {} {} ({})", name, version, capabilities.join(", "));
+
+        // clicked buttons and closed notifications are read from a single
+        // stream, because a click may close the notification
+        let rule = MatchRule::builder()
+            .msg_type(message::Type::Signal)
+            .interface(INTERFACE)?
+            .path(PATH)?
+            .build();
+        self.signals = match MessageStream::for_match_rule(rule, &self.connection, None).await {
+            Ok(signals) => Some(signals),
+            Err(err) => {
+                eprintln!("notification responses are not available: {}", err);
+                None
+            }
+        };
+        let mut capabilities = Capabilities::from(&capabilities);
+        capabilities.actions &= self.signals.is_some();
+        self.status.lock().unwrap().capabilities = Some(capabilities);
+        self.notifications = Some(notifications);
+        Ok(())
+    }
+
+    fn disconnect(&mut self) {
+        self.notifications = None;
+        self.signals = None;
+        self.ids.clear();
+        self.status.lock().unwrap().capabilities = None;
+    }
+
+    /* drops what the server does not support */
+    fn adapt(&self, notification: &mut Notification) {
+        let capabilities = match &self.status.lock().unwrap().capabilities {
+            Some(capabilities) => capabilities.clone(),
+            None => return,
+        };
+        if !capabilities.actions {
+            notification.actions.clear();
+        }
+        notification.hints.retain(|hint| match hint {
+            Hint::ImageData(_) | Hint::ImagePath(_) => capabilities.images,
+            Hint::Resident(_) => capabilities.persistence,
+            _ => true,
+        });
+    }
+
+    /* an update replaces the notification of the server, if it was shown */
+    async fn notify(&mut self, id: Id, update: bool, notification: &Notification) {
+        let notifications = match &self.notifications {
+            Some(notifications) => notifications.clone(),
+            // updates of notifications, which went to the terminal, are dropped
+            None if update => return,
+            None => {
+                self.fallback.show(notification);
+                return;
+            }
+        };
+        let mut notification = notification.clone();
+        self.adapt(&mut notification);
+        let replaces_id = if update { self.ids.get(&id).copied().unwrap_or(0) } else { 0 };
+        let hints = notification.hints.iter().map(Into::into).collect();
+        let result = notifications.notify(&notification.appname, replaces_id, &notification.icon,
+            &notification.summary, &notification.body, &notification.actions, hints, notification.timeout.into()).await;
+        match result {
+            Ok(server_id) => {
+                self.ids.insert(id, server_id);
+            }
+            Err(err) => {
+                eprintln!("notification failed: {}", err);
+                // the server went away
+                self.disconnect();
+                if !update {
+                    self.fallback.show(&notification);
+                }
+            }
+        }
+    }
+
+    async fn close(&mut self, id: Id) {
+        if let (Some(server_id), Some(notifications)) = (self.ids.remove(&id), &self.notifications) {
+            let _ = notifications.close_notification(server_id).await;
+        }
+    }
+
+    /* forwards clicked buttons and closed notifications of the daemon */
+    async fn respond(&mut self, signal: &message::Message) {
+        let body = signal.body();
+        let response = match signal.header().member().map(|member| member.as_str()) {
+            Some("ActionInvoked") => body.deserialize::<(u32, String)>().ok()
+                .map(|(id, action)| (id, Response::Action(action))),
+            Some("NotificationClosed") => body.deserialize::<(u32, u32)>().ok()
+                .map(|(id, _)| (id, Response::Closed)),
+            _ => None,
+        };
+        let Some((server_id, response)) = response else {
+            return;
+        };
+        let id = self.ids.iter().find(|(_, own)| **own == server_id).map(|(id, _)| *id);
+        if let Some(id) = id {
+            if response == Response::Closed {
+                self.ids.remove(&id);
+            }
+            let _ = self.responses.send((id, response)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{NearbyConfig, NotifierConfig};
+    use crate::hints::battery_hints;
+    use crate::testing::{paired_message, FakeNotifications, PrivateBus};
+
+    fn card(summary: &str) -> Notification {
+        let mut notification = Notification::new();
+        notification.summary(summary);
+        notification.action("connect", "Connect");
+        notification.hint(Hint::Resident(true));
+        notification.hint(Hint::Category("device".to_string()));
+        notification
+    }
+
+    async fn server(bus: &PrivateBus) -> (Server, mpsc::Receiver<(Id, Response)>) {
+        let (responses, receiver) = mpsc::channel(8);
+        let server = Server::new(bus.connect().await, responses, Terminal::new(&NotifierConfig::default())).await;
+        (server, receiver)
+    }
+
+    #[tokio::test]
+    async fn notifications_follow_the_capabilities() {
+        let bus = PrivateBus::start();
+        let fake = FakeNotifications::start(&bus, &["body", "actions", "persistence"]).await;
+        let (mut server, mut responses) = server(&bus).await;
+        assert!(server.has_actions());
+
+        let first = server.show(&card("First")).unwrap();
+        let second = server.show(&card("Second")).unwrap();
+        assert_eq!(server.update(first, &card("First again")), Some(first));
+        server.close(second);
+        assert_eq!(fake.calls.wait(4).await, [
+            "Notify 1 First [connect Connect] [category resident]",
+            "Notify 2 Second [connect Connect] [category resident]",
+            "Notify 1 First again [connect Connect] [category resident]",
+            "Close 2",
+        ]);
+
+        // the responses refer to the ids of the notifier
+        fake.click(1, "connect").await;
+        assert_eq!(responses.recv().await, Some((first, Response::Action("connect".to_string()))));
+        fake.dismiss(1).await;
+        assert_eq!(responses.recv().await, Some((first, Response::Closed)));
+    }
+
+    #[tokio::test]
+    async fn battery_hints_reach_the_server() {
+        let bus = PrivateBus::start();
+        let fake = FakeNotifications::start(&bus, &["body"]).await;
+        let (mut server, _responses) = server(&bus).await;
+        let mut notification = Notification::new();
+        notification.summary("Work");
+        battery_hints(&mut notification, &paired_message(5, 6, 8), &NearbyConfig::default(), "airpods-notify.Work");
+        server.show(&notification);
+        assert_eq!(fake.calls.wait(1).await, [
+            "Notify 1 Work [] [category urgency value x-canonical-private-synchronous x-dunst-stack-tag]",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn unsupported_features_are_dropped() {
+        let bus = PrivateBus::start();
+        let fake = FakeNotifications::start(&bus, &["body"]).await;
+        let (mut server, _responses) = server(&bus).await;
+        assert!(!server.has_actions());
+        server.show(&card("Plain"));
+        assert_eq!(fake.calls.wait(1).await, ["Notify 1 Plain [] [category]"]);
+    }
+
+    #[tokio::test]
+    async fn missing_server_is_found_again() {
+        let bus = PrivateBus::start();
+        let (mut server, _responses) = server(&bus).await;
+        assert_eq!(server.show(&card("Terminal")), None);
+        assert!(!server.retry());
+
+        let fake = FakeNotifications::start(&bus, &["actions"]).await;
+        server.checked -= RETRY;
+        assert!(!server.retry());
+        for _ in 0..500 {
+            if server.has_actions() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        // once, so the cards are shown again
+        assert!(server.retry());
+        assert!(!server.retry());
+        assert!(server.show(&card("Desktop")).is_some());
+        assert_eq!(fake.calls.wait(1).await, ["Notify 1 Desktop [connect Connect] [category]"]);
+    }
+}
@@ -0,0 +1,255 @@
+use crate::battery::{format_duration, DeviceBattery, Source};
+use crate::config::TemplateConfig;
+use crate::identity::Identity;
+use crate::proximity::{PairedMessage, Battery, Lid};
+use minijinja::syntax::SyntaxConfig;
+use minijinja::value::Serde;
+use minijinja::Environment;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+const SUMMARY: &str = "{{ nickname }}";
+const BODY: &str = "\
+{% if address %}{{ address }} · {{ 'connected to this machine' if connected else 'paired' }}
+{% endif %}
+{% if headset is not none %}Headset: {{ headset }}%
+{% endif %}
+{% for name, part in [('Left', left), ('Right', right), ('Case', case)] if part %}
+{{ name }}: {{ part.level }}% {{ part.symbol }} {{ part.charging_symbol }}
+{% endfor %}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolSet {
+    /* requires a Nerd Font, otherwise the glyphs are shown as boxes */
+    NerdFont,
+    Emoji,
+    Ascii,
+}
+
+impl SymbolSet {
+    fn level(&self, level: u8) -> &'static str {
+        let index = match level {
+            0..=1 => 0,
+            2..=3 => 1,
+            4..=5 => 2,
+            6..=7 => 3,
+            _ => 4,
+        };
+        match self {
+            SymbolSet::NerdFont => ["\u{f244}", "\u{f243}", "\u{f242}", "\u{f241}", "\u{f240}"][index],
+            SymbolSet::Emoji => ["🪫", "🪫", "🔋", "🔋", "🔋"][index],
+            SymbolSet::Ascii => ["[    ]", "[#   ]", "[##  ]", "[### ]", "[####]"][index],
+        }
+    }
+
+    fn charging(&self, charging: bool) -> &'static str {
+        match (self, charging) {
+            (SymbolSet::NerdFont, true) => "\u{f0e7}",
+            (SymbolSet::NerdFont, false) => "",
+            (SymbolSet::Emoji, true) => "⚡",
+            (SymbolSet::Emoji, false) => "",
+            (SymbolSet::Ascii, true) => "+",
+            (SymbolSet::Ascii, false) => "",
+        }
+    }
+}
+
+/* a bud or the case */
+#[derive(Serialize)]
+struct PartContext {
+    level: u8,
+    charging: bool,
+    symbol: &'static str,
+    charging_symbol: &'static str,
+}
+
+/* variables of the templates */
+#[derive(Serialize)]
+struct Context {
+    nickname: String,
+    /* name of the registered device */
+    device: Option<String>,
+    address: Option<String>,
+    connected: bool,
+    rssi: i16,
+    model: String,
+    color: String,
+    lid_open: bool,
+    lid_counter: u8,
+    in_ear: String,
+    in_case: String,
+    part: String,
+    left: Option<PartContext>,
+    right: Option<PartContext>,
+    case: Option<PartContext>,
+    /* exact level of the connected headset */
+    headset: Option<u8>,
+    talk_time: Option<String>,
+}
+
+/* data of a single advertisement which is rendered into a notification */
+pub struct Subject<'a> {
+    pub msg: &'a PairedMessage,
+    pub device: Option<&'a str>,
+    pub identity: Option<&'a Identity>,
+    pub rssi: i16,
+    pub battery: Option<&'a DeviceBattery>,
+}
+
+/* User-editable MiniJinja templates of the notification summary and body,
+ * with overrides per model. */
+pub struct Templates {
+    environment: Environment<'static>,
+    symbols: SymbolSet,
+}
+
+impl Templates {
+    pub fn new(config: &TemplateConfig) -> Result<Templates, Box<dyn Error>> {
+        let mut environment = Environment::new();
+        environment.set_syntax(SyntaxConfig::builder().trim_blocks(true).lstrip_blocks(true).build()?);
+        environment.add_template_owned("summary", config.summary.clone().unwrap_or(SUMMARY.to_string()))?;
+        environment.add_template_owned("body", config.body.clone().unwrap_or(BODY.to_string()))?;
+        for model in &config.model {
+            if let Some(summary) = &model.summary {
+                environment.add_template_owned(format!("summary.{:?}", model.model), summary.clone())?;
+            }
+            if let Some(body) = &model.body {
+                environment.add_template_owned(format!("body.{:?}", model.model), body.clone())?;
+            }
+        }
+        Ok(Templates {
+            environment,
+            symbols: config.symbols,
+        })
+    }
+
+    fn part(&self, level: Battery, charging: bool) -> Option<PartContext> {
+        match level {
+            Battery::Level(level) => Some(PartContext {
+                // the levels of the advertisement reach up to 10
+                level: level.min(10) * 10,
+                charging,
+                symbol: self.symbols.level(level),
+                charging_symbol: self.symbols.charging(charging),
+            }),
+            Battery::None => None,
+        }
+    }
+
+    fn context(&self, subject: &Subject) -> Context {
+        let msg = subject.msg;
+        let nickname = subject.identity.map(|identity| identity.alias.clone())
+            .or(subject.device.map(String::from))
+            .unwrap_or(format!("{:?}", msg.model));
+        let headset = subject.battery
+            .and_then(|battery| battery.headset())
+            .filter(|headset| headset.source == Source::BlueZ && headset.is_fresh());
+        let (lid_open, lid_counter) = match msg.lid {
+            Lid::Open(counter) => (true, counter),
+            Lid::Closed(counter) => (false, counter),
+        };
+        Context {
+            nickname,
+            device: subject.device.map(String::from),
+            address: subject.identity.map(|identity| identity.address.clone()),
+            connected: subject.identity.is_some_and(|identity| identity.connected),
+            rssi: subject.rssi,
+            model: msg.model.to_string(),
+            color: msg.color.to_string(),
+            lid_open,
+            lid_counter,
+            in_ear: msg.plugged_in_ear.to_string(),
+            in_case: msg.plugged_in_case.to_string(),
+            part: msg.part.to_string(),
+            left: self.part(msg.left_battery_level, msg.left_charging),
+            right: self.part(msg.right_battery_level, msg.right_charging),
+            case: self.part(msg.case_battery_level, msg.case_charging),
+            headset: headset.map(|headset| headset.percent),
+            talk_time: subject.battery
+                .and_then(|battery| battery.remaining_talk_time())
+                .map(format_duration),
+        }
+    }
+
+    /* the template of the model is preferred over the common one */
+    fn render(&self, name: &str, subject: &Subject) -> String {
+        let template = self.environment.get_template(&format!("{}.{:?}", name, subject.msg.model))
+            .or_else(|_| self.environment.get_template(name));
+        match template.and_then(|template| template.render(Serde(self.context(subject)))) {
+            Ok(text) => text,
+            Err(err) => {
+                eprintln!("{} template failed: {}", name, err);
+                format!("{:?}", subject.msg.model)
+            }
+        }
+    }
+
+    pub fn summary(&self, subject: &Subject) -> String {
+        self.render("summary", subject)
+    }
+
+    pub fn body(&self, subject: &Subject) -> String {
+        self.render("body", subject)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proximity::Model;
+    use crate::testing::paired_message;
+
+    fn templates(config: &str) -> Templates {
+        Templates::new(&toml::from_str(config).unwrap()).unwrap()
+    }
+
+    fn subject(msg: &PairedMessage) -> Subject<'_> {
+        Subject { msg, device: Some("Work"), identity: None, rssi: -60, battery: None }
+    }
+
+    #[test]
+    fn default_templates_list_the_parts() {
+        let msg = PairedMessage { case_battery_level: Battery::None, left_charging: true, ..paired_message(8, 3, 5) };
+        let templates = templates("symbols = \"ascii\"");
+        assert_eq!(templates.summary(&subject(&msg)), "Work");
+        assert_eq!(templates.body(&subject(&msg)), "Left: 80% [####] +\nRight: 30% [#   ] \n");
+    }
+
+    #[test]
+    fn symbol_sets_show_the_level() {
+        let msg = PairedMessage { right_charging: true, ..paired_message(1, 9, 5) };
+        let body = "{{ left.symbol }}|{{ right.symbol }}{{ right.charging_symbol }}|{{ case.symbol }}";
+        for (symbols, expected) in [
+            ("nerdfont", "\u{f244}|\u{f240}\u{f0e7}|\u{f242}"),
+            ("emoji", "🪫|🔋⚡|🔋"),
+            ("ascii", "[    ]|[####]+|[##  ]"),
+        ] {
+            let templates = templates(&format!("symbols = \"{}\"\nbody = \"{}\"", symbols, body));
+            assert_eq!(templates.body(&subject(&msg)), expected, "{}", symbols);
+        }
+    }
+
+    #[test]
+    fn levels_beyond_the_advertisement_are_clamped() {
+        let msg = paired_message(15, 255, 5);
+        let templates = templates("body = \"{{ left.level }} {{ right.level }}\"");
+        assert_eq!(templates.body(&subject(&msg)), "100 100");
+    }
+
+    #[test]
+    fn model_template_is_preferred() {
+        let templates = templates("summary = \"{{ device }}\"\n\
+            [[model]]\nmodel = \"AirPodsPro\"\nsummary = \"Pro {{ device }}\"\n\
+            [[model]]\nmodel = \"AirPodsMax\"\nsummary = \"Max {{ device }}\"\n");
+        assert_eq!(templates.summary(&subject(&paired_message(5, 5, 5))), "Pro Work");
+        let other = PairedMessage { model: Model::AirPods2, ..paired_message(5, 5, 5) };
+        assert_eq!(templates.summary(&subject(&other)), "Work");
+    }
+
+    #[test]
+    fn failing_template_falls_back_to_the_model() {
+        let templates = templates("summary = \"{{ left.level + 'x' }}\"");
+        assert_eq!(templates.summary(&subject(&paired_message(5, 5, 5))), "AirPodsPro");
+    }
+}
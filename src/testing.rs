@@ -0,0 +1,729 @@
+/* Stand-ins for the D-Bus services of the tests, served on a private bus */
+use crate::audio::AudioBackend;
+use crate::proximity::{Battery, Color, Lid, Model, PairedMessage, Part, Plugged};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use zbus::fdo::ObjectManagerProxy;
+use zbus::message::Header;
+use zbus::names::OwnedUniqueName;
+use zbus::object_server::ObjectServer;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+use zbus::{connection, interface, Connection};
+
+pub const ADAPTER_PATH: &str = "/org/bluez/hci0";
+
+/* report of an open AirPods Pro case with both buds worn, levels in 10% steps */
+pub fn paired_message(left: u8, right: u8, case: u8) -> PairedMessage {
+    PairedMessage {
+        model: Model::AirPodsPro,
+        lid: Lid::Open(1),
+        color: Color::White,
+        case_battery_level: Battery::Level(case),
+        left_battery_level: Battery::Level(left),
+        right_battery_level: Battery::Level(right),
+        case_charging: false,
+        right_charging: false,
+        left_charging: false,
+        plugged_in_ear: Plugged::Both,
+        plugged_in_case: Plugged::None,
+        part: Part::LeftEarPlug,
+    }
+}
+
+/* dbus-daemon of a single test, stopped when dropped */
+pub struct PrivateBus {
+    daemon: Child,
+    address: String,
+}
+
+impl PrivateBus {
+    pub fn start() -> PrivateBus {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("dbus-daemon is required for the tests");
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address).unwrap();
+        PrivateBus {
+            daemon,
+            address: address.trim().to_string(),
+        }
+    }
+
+    pub async fn connect(&self) -> Connection {
+        connection::Builder::address(self.address.as_str()).unwrap()
+            .build()
+            .await
+            .unwrap()
+    }
+
+    /* connection which owns the given name */
+    pub async fn serve(&self, name: &str) -> Connection {
+        connection::Builder::address(self.address.as_str()).unwrap()
+            .name(name).unwrap()
+            .build()
+            .await
+            .unwrap()
+    }
+}
+
+impl Drop for PrivateBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+/* method calls received by a stand-in, in order */
+#[derive(Debug, Clone, Default)]
+pub struct Calls(Arc<Mutex<Vec<String>>>);
+
+impl Calls {
+    pub fn push(&self, call: impl Into<String>) {
+        self.0.lock().unwrap().push(call.into());
+    }
+
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+
+    /* takes the calls once there are at least the given number, e.g. of a task */
+    pub async fn wait(&self, count: usize) -> Vec<String> {
+        for _ in 0..500 {
+            if self.0.lock().unwrap().len() >= count {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        self.take()
+    }
+}
+
+/* e.g. AA:BB:CC:DD:EE:FF -> /org/bluez/hci0/dev_AA_BB_CC_DD_EE_FF */
+pub fn device_path(address: &str) -> OwnedObjectPath {
+    OwnedObjectPath::try_from(format!("{}/dev_{}", ADAPTER_PATH, address.replace(':', "_"))).unwrap()
+}
+
+#[derive(Debug, Clone)]
+pub struct MockDevice {
+    pub address: String,
+    pub alias: String,
+    pub paired: bool,
+    pub connected: bool,
+    pub trusted: bool,
+    pub modalias: String,
+    pub calls: Calls,
+}
+
+impl MockDevice {
+    /* an AirPods Pro, which is neither paired nor connected */
+    pub fn new(address: &str, alias: &str) -> MockDevice {
+        MockDevice {
+            address: address.to_string(),
+            alias: alias.to_string(),
+            paired: false,
+            connected: false,
+            trusted: false,
+            modalias: "bluetooth:v004Cp200Ed0A10".to_string(),
+            calls: Calls::default(),
+        }
+    }
+}
+
+#[interface(name = "org.bluez.Device1")]
+impl MockDevice {
+    #[zbus(property)]
+    fn address(&self) -> &str {
+        &self.address
+    }
+
+    #[zbus(property)]
+    fn alias(&self) -> &str {
+        &self.alias
+    }
+
+    #[zbus(property)]
+    fn paired(&self) -> bool {
+        self.paired
+    }
+
+    #[zbus(property)]
+    fn connected(&self) -> bool {
+        self.connected
+    }
+
+    #[zbus(property)]
+    fn trusted(&self) -> bool {
+        self.trusted
+    }
+
+    #[zbus(property)]
+    fn set_trusted(&mut self, trusted: bool) {
+        self.calls.push(format!("Trust {}", self.address));
+        self.trusted = trusted;
+    }
+
+    #[zbus(property)]
+    fn modalias(&self) -> &str {
+        &self.modalias
+    }
+
+    fn pair(&mut self) {
+        self.calls.push(format!("Pair {}", self.address));
+        self.paired = true;
+    }
+
+    fn connect(&mut self) {
+        self.calls.push(format!("Connect {}", self.address));
+        self.connected = true;
+    }
+
+    fn disconnect(&mut self) {
+        self.calls.push(format!("Disconnect {}", self.address));
+        self.connected = false;
+    }
+}
+
+/* org.bluez.Battery1 of a device */
+pub struct MockBattery {
+    pub percentage: u8,
+}
+
+#[interface(name = "org.bluez.Battery1")]
+impl MockBattery {
+    #[zbus(property)]
+    fn percentage(&self) -> u8 {
+        self.percentage
+    }
+}
+
+/* the adapter finds the pending device once discovery starts */
+struct MockAdapter {
+    pending: Option<MockDevice>,
+    /* the objects can no longer be listed once discovery starts */
+    failing: bool,
+    calls: Calls,
+}
+
+#[interface(name = "org.bluez.Adapter1")]
+impl MockAdapter {
+    async fn start_discovery(
+        &mut self,
+        #[zbus(object_server)] server: &ObjectServer,
+        #[zbus(connection)] connection: &Connection,
+    ) -> zbus::fdo::Result<()> {
+        self.calls.push("StartDiscovery");
+        if let Some(device) = self.pending.take() {
+            server.at(device_path(&device.address), device).await?;
+        }
+        if self.failing {
+            // the manager would wait for this call to finish
+            let server = server.clone();
+            connection.executor().spawn(async move {
+                let _ = server.at("/", NoObjectManager).await;
+                let _ = server.remove::<zbus::fdo::ObjectManager, _>("/").await;
+            }, "remove object manager").detach();
+        }
+        Ok(())
+    }
+
+    fn stop_discovery(&self) {
+        self.calls.push("StopDiscovery");
+    }
+}
+
+/* takes the place of the object manager, so the objects cannot be listed */
+struct NoObjectManager;
+
+#[interface(name = "org.bluez.Test1")]
+impl NoObjectManager {}
+
+/* remembers the registered provider, whose objects are read on demand */
+#[derive(Default)]
+struct MockBatteryProviderManager {
+    provider: Option<(OwnedUniqueName, OwnedObjectPath)>,
+}
+
+#[interface(name = "org.bluez.BatteryProviderManager1")]
+impl MockBatteryProviderManager {
+    fn register_battery_provider(&mut self, provider: ObjectPath<'_>, #[zbus(header)] header: Header<'_>) {
+        let sender = header.sender().unwrap().to_owned();
+        self.provider = Some((sender.into(), provider.into()));
+    }
+}
+
+struct MockAgentManager {
+    /* a single agent at a time, like BlueZ for each connection */
+    agent: Option<String>,
+    calls: Calls,
+}
+
+#[interface(name = "org.bluez.AgentManager1")]
+impl MockAgentManager {
+    fn register_agent(&mut self, agent: ObjectPath<'_>, capability: &str) -> zbus::fdo::Result<()> {
+        self.calls.push(format!("RegisterAgent {} {}", agent, capability));
+        if self.agent.is_some() {
+            return Err(zbus::fdo::Error::Failed("Already Exists".to_string()));
+        }
+        self.agent = Some(agent.to_string());
+        Ok(())
+    }
+
+    fn unregister_agent(&mut self, agent: ObjectPath<'_>) {
+        self.calls.push(format!("UnregisterAgent {}", agent));
+        self.agent = None;
+    }
+}
+
+/* org.bluez with a single adapter */
+pub struct MockBlueZ {
+    pub connection: Connection,
+    pub calls: Calls,
+}
+
+impl MockBlueZ {
+    pub async fn start(bus: &PrivateBus) -> MockBlueZ {
+        let connection = bus.serve("org.bluez").await;
+        let calls = Calls::default();
+        let server = connection.object_server();
+        server.at("/", zbus::fdo::ObjectManager).await.unwrap();
+        server.at("/org/bluez", MockAgentManager { agent: None, calls: calls.clone() }).await.unwrap();
+        server.at(ADAPTER_PATH, MockAdapter { pending: None, failing: false, calls: calls.clone() }).await.unwrap();
+        server.at(ADAPTER_PATH, MockBatteryProviderManager::default()).await.unwrap();
+        MockBlueZ { connection, calls }
+    }
+
+    pub async fn add_device(&self, mut device: MockDevice, battery: Option<u8>) {
+        let path = device_path(&device.address);
+        device.calls = self.calls.clone();
+        let server = self.connection.object_server();
+        server.at(&path, device).await.unwrap();
+        if let Some(percentage) = battery {
+            server.at(&path, MockBattery { percentage }).await.unwrap();
+        }
+    }
+
+    /* the device appears only while discovering */
+    pub async fn add_discoverable(&self, mut device: MockDevice) {
+        device.calls = self.calls.clone();
+        let adapter = self.connection.object_server()
+            .interface::<_, MockAdapter>(ADAPTER_PATH).await.unwrap();
+        adapter.get_mut().await.pending = Some(device);
+    }
+
+    pub async fn fail_discovery(&self) {
+        let adapter = self.connection.object_server()
+            .interface::<_, MockAdapter>(ADAPTER_PATH).await.unwrap();
+        adapter.get_mut().await.failing = true;
+    }
+
+    /* device and percentage of the batteries of the registered provider */
+    pub async fn provided_batteries(&self) -> Vec<(String, u8)> {
+        let manager = self.connection.object_server()
+            .interface::<_, MockBatteryProviderManager>(ADAPTER_PATH).await.unwrap();
+        let Some((sender, path)) = manager.get().await.provider.clone() else {
+            return Vec::new();
+        };
+        let objects = ObjectManagerProxy::builder(&self.connection)
+            .destination(sender).unwrap()
+            .path(path).unwrap()
+            .build()
+            .await
+            .unwrap()
+            .get_managed_objects()
+            .await
+            .unwrap();
+        let mut batteries: Vec<(String, u8)> = objects.values()
+            .filter_map(|interfaces| interfaces.iter().find(|(name, _)| name.as_str() == "org.bluez.BatteryProvider1"))
+            .map(|(_, properties)| {
+                let device = OwnedObjectPath::try_from(properties["Device"].clone()).unwrap();
+                (device.to_string(), u8::try_from(&properties["Percentage"]).unwrap())
+            })
+            .collect();
+        batteries.sort();
+        batteries
+    }
+
+    pub async fn device(&self, address: &str) -> MockDevice {
+        let device = self.connection.object_server()
+            .interface::<_, MockDevice>(device_path(address)).await.unwrap();
+        let device = device.get().await.clone();
+        device
+    }
+}
+
+/* org.freedesktop.login1.Manager with a settable idle hint */
+struct FakeManager {
+    idle: bool,
+}
+
+#[interface(name = "org.freedesktop.login1.Manager")]
+impl FakeManager {
+    #[zbus(property)]
+    fn idle_hint(&self) -> bool {
+        self.idle
+    }
+}
+
+/* the session of the caller, which records lock requests */
+struct FakeSession {
+    locked: bool,
+    calls: Calls,
+}
+
+#[interface(name = "org.freedesktop.login1.Session")]
+impl FakeSession {
+    fn lock(&mut self) {
+        self.calls.push("Lock");
+        self.locked = true;
+    }
+
+    #[zbus(property)]
+    fn locked_hint(&self) -> bool {
+        self.locked
+    }
+}
+
+pub struct FakeLogind {
+    pub connection: Connection,
+    pub calls: Calls,
+}
+
+impl FakeLogind {
+    pub async fn start(bus: &PrivateBus) -> FakeLogind {
+        let connection = bus.serve("org.freedesktop.login1").await;
+        let calls = Calls::default();
+        let server = connection.object_server();
+        server.at("/org/freedesktop/login1", FakeManager { idle: false }).await.unwrap();
+        server.at("/org/freedesktop/login1/session/auto", FakeSession { locked: false, calls: calls.clone() }).await.unwrap();
+        FakeLogind { connection, calls }
+    }
+
+    pub async fn set_idle(&self, idle: bool) {
+        let manager = self.connection.object_server()
+            .interface::<_, FakeManager>("/org/freedesktop/login1").await.unwrap();
+        manager.get_mut().await.idle = idle;
+    }
+
+    pub async fn set_locked(&self, locked: bool) {
+        let session = self.connection.object_server()
+            .interface::<_, FakeSession>("/org/freedesktop/login1/session/auto").await.unwrap();
+        session.get_mut().await.locked = locked;
+    }
+}
+
+/* the do-not-disturb state of KDE Plasma */
+struct FakeInhibition {
+    inhibited: bool,
+}
+
+#[interface(name = "org.freedesktop.Notifications")]
+impl FakeInhibition {
+    #[zbus(property)]
+    fn inhibited(&self) -> bool {
+        self.inhibited
+    }
+}
+
+/* the do-not-disturb state of dunst */
+struct FakeDunstControl {
+    paused: bool,
+}
+
+#[interface(name = "org.dunstproject.cmd0")]
+impl FakeDunstControl {
+    #[zbus(property)]
+    fn paused(&self) -> bool {
+        self.paused
+    }
+}
+
+/* a notification server with the do-not-disturb state of Plasma and dunst */
+pub struct FakeDoNotDisturb {
+    pub connection: Connection,
+}
+
+impl FakeDoNotDisturb {
+    pub async fn start(bus: &PrivateBus) -> FakeDoNotDisturb {
+        let connection = bus.serve("org.freedesktop.Notifications").await;
+        let server = connection.object_server();
+        server.at("/org/freedesktop/Notifications", FakeInhibition { inhibited: false }).await.unwrap();
+        server.at("/org/freedesktop/Notifications", FakeDunstControl { paused: false }).await.unwrap();
+        FakeDoNotDisturb { connection }
+    }
+
+    pub async fn set_inhibited(&self, inhibited: bool) {
+        let inhibition = self.connection.object_server()
+            .interface::<_, FakeInhibition>("/org/freedesktop/Notifications").await.unwrap();
+        inhibition.get_mut().await.inhibited = inhibited;
+    }
+
+    pub async fn set_paused(&self, paused: bool) {
+        let dunst = self.connection.object_server()
+            .interface::<_, FakeDunstControl>("/org/freedesktop/Notifications").await.unwrap();
+        dunst.get_mut().await.paused = paused;
+    }
+}
+
+/* org.freedesktop.Notifications, which records the notifications with
+ * their actions and the names of their hints */
+struct FakeNotificationServer {
+    capabilities: Vec<String>,
+    next: u32,
+    calls: Calls,
+}
+
+#[interface(name = "org.freedesktop.Notifications")]
+impl FakeNotificationServer {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &mut self,
+        _app_name: &str,
+        replaces_id: u32,
+        _app_icon: &str,
+        summary: &str,
+        _body: &str,
+        actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        _expire_timeout: i32,
+    ) -> u32 {
+        let id = match replaces_id {
+            0 => {
+                self.next += 1;
+                self.next
+            }
+            id => id,
+        };
+        let mut hints: Vec<String> = hints.into_keys().collect();
+        hints.sort();
+        self.calls.push(format!("Notify {} {} [{}] [{}]", id, summary, actions.join(" "), hints.join(" ")));
+        id
+    }
+
+    fn close_notification(&self, id: u32) {
+        self.calls.push(format!("Close {}", id));
+    }
+
+    fn get_capabilities(&self) -> Vec<String> {
+        self.capabilities.clone()
+    }
+
+    fn get_server_information(&self) -> (String, String, String, String) {
+        ("fake".to_string(), "test".to_string(), "1.0".to_string(), "1.2".to_string())
+    }
+
+    #[zbus(signal)]
+    async fn action_invoked(emitter: &SignalEmitter<'_>, id: u32, action_key: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn notification_closed(emitter: &SignalEmitter<'_>, id: u32, reason: u32) -> zbus::Result<()>;
+}
+
+pub struct FakeNotifications {
+    pub connection: Connection,
+    pub calls: Calls,
+}
+
+impl FakeNotifications {
+    pub async fn start(bus: &PrivateBus, capabilities: &[&str]) -> FakeNotifications {
+        let connection = bus.serve("org.freedesktop.Notifications").await;
+        let calls = Calls::default();
+        let server = FakeNotificationServer {
+            capabilities: capabilities.iter().map(|capability| capability.to_string()).collect(),
+            next: 0,
+            calls: calls.clone(),
+        };
+        connection.object_server().at("/org/freedesktop/Notifications", server).await.unwrap();
+        FakeNotifications { connection, calls }
+    }
+
+    /* a click on a button, identified by the id of the server */
+    pub async fn click(&self, id: u32, action: &str) {
+        let emitter = SignalEmitter::new(&self.connection, "/org/freedesktop/Notifications").unwrap();
+        FakeNotificationServer::action_invoked(&emitter, id, action).await.unwrap();
+    }
+
+    pub async fn dismiss(&self, id: u32) {
+        let emitter = SignalEmitter::new(&self.connection, "/org/freedesktop/Notifications").unwrap();
+        FakeNotificationServer::notification_closed(&emitter, id, 2).await.unwrap();
+    }
+}
+
+/* org.freedesktop.portal.Notification, which records the entries of the
+ * notifications in the order title, body, priority, icon and buttons */
+struct FakePortalNotification {
+    calls: Calls,
+}
+
+/* the value of a themed icon or of a serialized image */
+fn icon(value: OwnedValue) -> String {
+    match <(String, OwnedValue)>::try_from(value) {
+        Ok((kind, names)) if kind == "themed" => {
+            // the variant of the structure is read as a nested variant
+            let names = match &*names {
+                Value::Value(names) => names.try_clone().ok().and_then(|names| Vec::<String>::try_from(names).ok()),
+                names => names.try_clone().ok().and_then(|names| Vec::<String>::try_from(names).ok()),
+            };
+            format!("themed:{}", names.unwrap_or_default().join(","))
+        }
+        Ok((kind, _)) => kind,
+        Err(_) => "invalid".to_string(),
+    }
+}
+
+#[interface(name = "org.freedesktop.portal.Notification")]
+impl FakePortalNotification {
+    fn add_notification(&self, id: &str, mut notification: HashMap<String, OwnedValue>) {
+        let mut entries = vec![format!("Add {}", id)];
+        for key in ["title", "body", "priority", "default-action"] {
+            if let Some(value) = notification.remove(key).and_then(|value| String::try_from(value).ok()) {
+                entries.push(format!("{}={}", key, value));
+            }
+        }
+        if let Some(value) = notification.remove("icon") {
+            entries.push(format!("icon={}", icon(value)));
+        }
+        if let Some(buttons) = notification.remove("buttons").and_then(|value| Vec::<HashMap<String, OwnedValue>>::try_from(value).ok()) {
+            let buttons: Vec<String> = buttons.into_iter()
+                .map(|mut button| {
+                    let mut entry = |key| button.remove(key).and_then(|value| String::try_from(value).ok()).unwrap_or_default();
+                    format!("{}:{}", entry("action"), entry("label"))
+                })
+                .collect();
+            entries.push(format!("buttons={}", buttons.join(",")));
+        }
+        // entries which are not known to the portal
+        let mut unknown: Vec<String> = notification.into_keys().collect();
+        unknown.sort();
+        entries.extend(unknown);
+        self.calls.push(entries.join(" "));
+    }
+
+    fn remove_notification(&self, id: &str) {
+        self.calls.push(format!("Remove {}", id));
+    }
+
+    #[zbus(property)]
+    fn version(&self) -> u32 {
+        2
+    }
+
+    #[zbus(signal)]
+    async fn action_invoked(emitter: &SignalEmitter<'_>, id: &str, action: &str, parameter: Vec<OwnedValue>) -> zbus::Result<()>;
+}
+
+pub struct FakePortal {
+    pub connection: Connection,
+    pub calls: Calls,
+}
+
+impl FakePortal {
+    pub async fn start(bus: &PrivateBus) -> FakePortal {
+        let connection = bus.serve("org.freedesktop.portal.Desktop").await;
+        let calls = Calls::default();
+        let portal = FakePortalNotification { calls: calls.clone() };
+        connection.object_server().at("/org/freedesktop/portal/desktop", portal).await.unwrap();
+        FakePortal { connection, calls }
+    }
+
+    pub async fn click(&self, id: &str, action: &str) {
+        let emitter = SignalEmitter::new(&self.connection, "/org/freedesktop/portal/desktop").unwrap();
+        FakePortalNotification::action_invoked(&emitter, id, action, Vec::new()).await.unwrap();
+    }
+}
+
+pub const SPEAKERS: &str = "alsa_output.pci-0000_00_1f.3.analog-stereo";
+pub const HEADSET: &str = "bluez_output.AA_BB_CC_DD_EE_01.1";
+
+/* sound server with fixed sinks, which records all changes */
+pub struct RecordingBackend {
+    pub sinks: Vec<String>,
+    pub default: String,
+    /* profile of the headset card */
+    pub profile: Option<String>,
+    pub calls: Calls,
+}
+
+impl RecordingBackend {
+    /* the first sink is the default */
+    pub fn new(sinks: &[&str]) -> RecordingBackend {
+        RecordingBackend {
+            sinks: sinks.iter().map(|sink| sink.to_string()).collect(),
+            default: sinks[0].to_string(),
+            profile: None,
+            calls: Calls::default(),
+        }
+    }
+}
+
+impl AudioBackend for RecordingBackend {
+    async fn sinks(&mut self) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(self.sinks.clone())
+    }
+
+    async fn default_sink(&mut self) -> Result<String, Box<dyn Error>> {
+        Ok(self.default.clone())
+    }
+
+    async fn set_default_sink(&mut self, sink: &str) -> Result<(), Box<dyn Error>> {
+        self.calls.push(format!("default {}", sink));
+        self.default = sink.to_string();
+        Ok(())
+    }
+
+    async fn profile(&mut self, _address: &str) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(self.profile.clone())
+    }
+
+    async fn set_profile(&mut self, address: &str, profile: &str) -> Result<(), Box<dyn Error>> {
+        self.calls.push(format!("profile {} {}", address, profile));
+        self.profile = Some(profile.to_string());
+        Ok(())
+    }
+}
+
+const PLAYER_PATH: &str = "/org/mpris/MediaPlayer2";
+
+/* MPRIS player with a fixed status, which records pause and play */
+pub struct FakePlayer {
+    name: &'static str,
+    status: &'static str,
+    calls: Calls,
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl FakePlayer {
+    fn pause(&mut self) {
+        self.calls.push(format!("Pause {}", self.name));
+        self.status = "Paused";
+    }
+
+    fn play(&mut self) {
+        self.calls.push(format!("Play {}", self.name));
+        self.status = "Playing";
+    }
+
+    #[zbus(property)]
+    fn playback_status(&self) -> &str {
+        self.status
+    }
+}
+
+impl FakePlayer {
+    pub async fn start(bus: &PrivateBus, name: &'static str, status: &'static str, calls: &Calls) -> Connection {
+        let connection = bus.serve(&format!("org.mpris.MediaPlayer2.{}", name)).await;
+        connection.object_server().at(PLAYER_PATH, FakePlayer { name, status, calls: calls.clone() }).await.unwrap();
+        connection
+    }
+
+    pub async fn set_status(player: &Connection, status: &'static str) {
+        let player = player.object_server().interface::<_, FakePlayer>(PLAYER_PATH).await.unwrap();
+        player.get_mut().await.status = status;
+    }
+}
@@ -0,0 +1,225 @@
+use crate::config::{DeviceConfig, LeftBehindConfig};
+use crate::proximity::{PairedMessage, Battery, Lid, Part, Plugged};
+use chrono::{DateTime, Local};
+use std::collections::HashMap;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sighting {
+    pub at: DateTime<Local>,
+    pub instant: Instant,
+    pub rssi: i16,
+}
+
+impl Sighting {
+    fn at(instant: Instant, rssi: i16) -> Sighting {
+        Sighting {
+            at: Local::now(),
+            instant,
+            rssi,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LeftBehind {
+    /* one bud is not reported anymore by the other one */
+    Bud { device: String, part: Part, last_seen: Sighting },
+    /* the headset does not advertise anymore */
+    Headset { device: String, last_seen: Sighting },
+}
+
+impl LeftBehind {
+    pub fn summary(&self) -> String {
+        match self {
+            LeftBehind::Bud { device, part, .. } => format!("{} left behind: {}", device, part_name(part)),
+            LeftBehind::Headset { device, .. } => format!("{} left behind", device),
+        }
+    }
+
+    pub fn body(&self) -> String {
+        let last_seen = match self {
+            LeftBehind::Bud { last_seen, .. } => last_seen,
+            LeftBehind::Headset { last_seen, .. } => last_seen,
+        };
+        format!("Last seen at {} with {} dBm", last_seen.at.format("%H:%M"), last_seen.rssi)
+    }
+}
+
+fn part_name(part: &Part) -> &'static str {
+    match part {
+        Part::LeftEarPlug => "left bud",
+        Part::RightEarPlug => "right bud",
+        Part::Headphones => "headphones",
+    }
+}
+
+#[derive(Debug, Default)]
+struct Presence {
+    last_seen: Option<Sighting>,
+    /* a closed case with both buds inside stops advertising by design */
+    stored: bool,
+    left: Option<Sighting>,
+    right: Option<Sighting>,
+    /* a bud was missing since this time */
+    separated_since: Option<Instant>,
+    bud_alerted: bool,
+    headset_alerted: bool,
+}
+
+/* Tracks the presence of the registered devices and their buds */
+pub struct Tracker {
+    config: LeftBehindConfig,
+    devices: HashMap<String, Presence>,
+}
+
+impl Tracker {
+    pub fn new(config: LeftBehindConfig) -> Tracker {
+        Tracker {
+            config,
+            devices: HashMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn update(&mut self, device: &DeviceConfig, msg: &PairedMessage, rssi: i16) {
+        self.update_at(device, msg, rssi, Instant::now());
+    }
+
+    fn update_at(&mut self, device: &DeviceConfig, msg: &PairedMessage, rssi: i16, now: Instant) {
+        let presence = self.devices.entry(device.name.clone()).or_default();
+        let sighting = Sighting::at(now, rssi);
+        presence.last_seen = Some(sighting);
+        presence.headset_alerted = false;
+        presence.stored = matches!(msg.lid, Lid::Closed(_)) && msg.plugged_in_case == Plugged::Both;
+
+        if msg.model.is_single_device() {
+            return;
+        }
+        let left = msg.left_battery_level != Battery::None;
+        let right = msg.right_battery_level != Battery::None;
+        if left {
+            presence.left = Some(sighting);
+        }
+        if right {
+            presence.right = Some(sighting);
+        }
+        if left == right {
+            presence.separated_since = None;
+            presence.bud_alerted = false;
+        } else if presence.separated_since.is_none() {
+            presence.separated_since = Some(sighting.instant);
+        }
+    }
+
+    /* alerts are raised once, until the device or bud is seen again */
+    pub fn check(&mut self, laptop_active: bool) -> Vec<LeftBehind> {
+        self.check_at(laptop_active, Instant::now())
+    }
+
+    fn check_at(&mut self, laptop_active: bool, now: Instant) -> Vec<LeftBehind> {
+        let separation = self.config.separation();
+        let absence = self.config.absence();
+        let mut alerts = Vec::new();
+
+        for (name, presence) in self.devices.iter_mut() {
+            // the bud must have been seen together with the other one before
+            if let (Some(since), Some(left), Some(right)) = (presence.separated_since, presence.left, presence.right) {
+                if !presence.bud_alerted && now.duration_since(since) >= separation {
+                    let (part, last_seen) = if left.instant < right.instant {
+                        (Part::LeftEarPlug, left)
+                    } else {
+                        (Part::RightEarPlug, right)
+                    };
+                    presence.bud_alerted = true;
+                    alerts.push(LeftBehind::Bud { device: name.clone(), part, last_seen });
+                }
+            }
+            if let Some(last_seen) = presence.last_seen {
+                if laptop_active && !presence.stored && !presence.headset_alerted
+                    && now.duration_since(last_seen.instant) >= absence {
+                    presence.headset_alerted = true;
+                    alerts.push(LeftBehind::Headset { device: name.clone(), last_seen });
+                }
+            }
+        }
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::paired_message;
+    use std::time::Duration;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn device() -> DeviceConfig {
+        toml::from_str("name = \"Work\"\nmodel = \"AirPodsPro\"\n").unwrap()
+    }
+
+    fn left_only() -> PairedMessage {
+        PairedMessage { right_battery_level: Battery::None, ..paired_message(5, 5, 5) }
+    }
+
+    #[test]
+    fn absent_headset_is_alerted_once_until_seen_again() {
+        let mut tracker = Tracker::new(LeftBehindConfig::default());
+        let start = Instant::now();
+        tracker.update_at(&device(), &paired_message(5, 5, 5), -60, start);
+        assert!(tracker.check_at(true, start + 9 * MINUTE).is_empty());
+
+        let alerts = tracker.check_at(true, start + 10 * MINUTE);
+        assert!(matches!(alerts.as_slice(), [LeftBehind::Headset { device, last_seen }] if device == "Work" && last_seen.rssi == -60));
+        assert_eq!(alerts[0].summary(), "Work left behind");
+        assert!(tracker.check_at(true, start + 20 * MINUTE).is_empty());
+
+        // seen again, so it may be left behind again
+        tracker.update_at(&device(), &paired_message(5, 5, 5), -60, start + 21 * MINUTE);
+        assert!(tracker.check_at(true, start + 30 * MINUTE).is_empty());
+        assert_eq!(tracker.check_at(true, start + 31 * MINUTE).len(), 1);
+    }
+
+    #[test]
+    fn stored_headset_or_idle_laptop_is_not_alerted() {
+        let mut tracker = Tracker::new(LeftBehindConfig::default());
+        let start = Instant::now();
+        tracker.update_at(&device(), &paired_message(5, 5, 5), -60, start);
+        assert!(tracker.check_at(false, start + 10 * MINUTE).is_empty());
+
+        let stored = PairedMessage { lid: Lid::Closed(1), plugged_in_case: Plugged::Both, ..paired_message(5, 5, 5) };
+        tracker.update_at(&device(), &stored, -60, start + 11 * MINUTE);
+        assert!(tracker.check_at(true, start + 30 * MINUTE).is_empty());
+    }
+
+    #[test]
+    fn separated_bud_is_alerted_once_until_both_are_seen() {
+        let mut tracker = Tracker::new(LeftBehindConfig::default());
+        let start = Instant::now();
+        tracker.update_at(&device(), &paired_message(5, 5, 5), -60, start);
+        tracker.update_at(&device(), &left_only(), -60, start + MINUTE);
+        assert!(tracker.check_at(true, start + 2 * MINUTE).is_empty());
+
+        let alerts = tracker.check_at(true, start + 3 * MINUTE);
+        assert!(matches!(alerts.as_slice(), [LeftBehind::Bud { part: Part::RightEarPlug, last_seen, .. }] if last_seen.instant == start));
+        assert_eq!(alerts[0].summary(), "Work left behind: right bud");
+        assert!(tracker.check_at(true, start + 4 * MINUTE).is_empty());
+
+        tracker.update_at(&device(), &paired_message(5, 5, 5), -60, start + 5 * MINUTE);
+        tracker.update_at(&device(), &left_only(), -60, start + 6 * MINUTE);
+        assert!(tracker.check_at(true, start + 7 * MINUTE).is_empty());
+        assert_eq!(tracker.check_at(true, start + 8 * MINUTE).len(), 1);
+    }
+
+    #[test]
+    fn bud_never_seen_together_is_not_alerted() {
+        let mut tracker = Tracker::new(LeftBehindConfig::default());
+        let start = Instant::now();
+        tracker.update_at(&device(), &left_only(), -60, start);
+        assert!(tracker.check_at(true, start + 5 * MINUTE).is_empty());
+    }
+}
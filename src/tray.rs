@@ -0,0 +1,557 @@
+use crate::config::{DeviceConfig, NearbyConfig, TrayConfig};
+use crate::details::{components, describe};
+use crate::identity::Identity;
+use crate::nearby::{Card, Seen};
+use crate::proximity::PairedMessage;
+use btleplug::api::BDAddr;
+use futures::StreamExt;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::process;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{ObjectPath, OwnedValue, Type, Value};
+use zbus::{fdo, interface, proxy, Connection};
+
+const ITEM_PATH: &str = "/StatusNotifierItem";
+const MENU_PATH: &str = "/MenuBar";
+
+/* ids of the menu entries, 0 is the root */
+const MENU_CONNECT: i32 = 1;
+const MENU_MONITOR: i32 = 2;
+const MENU_MUTE: i32 = 3;
+
+#[proxy(
+    interface = "org.kde.StatusNotifierWatcher",
+    default_service = "org.kde.StatusNotifierWatcher",
+    default_path = "/StatusNotifierWatcher"
+)]
+trait StatusNotifierWatcher {
+    fn register_status_notifier_item(&self, service: &str) -> zbus::Result<()>;
+}
+
+/* what the user chose in the tray */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrayAction {
+    Connect,
+    Disconnect,
+    Details,
+    Monitor,
+    Mute,
+    Unmute,
+}
+
+/* icon and tooltip of the headset */
+#[derive(Debug, Clone, PartialEq)]
+struct Look {
+    icon: String,
+    status: &'static str,
+    text: String,
+}
+
+/* thresholds of the battery icon */
+#[derive(Debug, Clone, Copy)]
+pub struct Levels {
+    critical: u8,
+    low: u8,
+    full: u8,
+}
+
+impl Levels {
+    /* the critical level is the one of the notifications */
+    pub fn new(tray: &TrayConfig, nearby: &NearbyConfig) -> Levels {
+        Levels {
+            critical: nearby.critical_percent,
+            low: tray.low_percent,
+            full: tray.full_percent,
+        }
+    }
+}
+
+/* themed battery icon of the lowest part, the status asks for attention when it runs out */
+fn look<H>(card: Option<&Card<H>>, levels: Levels) -> Look {
+    let card = match card {
+        Some(card) => card,
+        None => return Look {
+            icon: "audio-headphones".to_string(),
+            status: "Passive",
+            text: "not seen".to_string(),
+        },
+    };
+    let lowest = [card.left, card.right].into_iter()
+        .flatten()
+        .min_by_key(|seen: &Seen| seen.percent);
+    let text = components(card).into_iter()
+        .map(|(component, seen)| match seen {
+            Some(seen) => format!("{}: {}", component, describe(&seen)),
+            None => format!("{}: not seen", component),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let (icon, status) = match lowest {
+        None => ("audio-headphones".to_string(), "Active"),
+        Some(seen) => {
+            let level = match seen.percent {
+                percent if percent <= levels.critical => "caution",
+                percent if percent <= levels.low => "low",
+                percent if percent < levels.full => "good",
+                _ => "full",
+            };
+            let critical = seen.percent <= levels.critical && !seen.charging;
+            let charging = if seen.charging { "-charging" } else { "" };
+            (format!("battery-{}{}", level, charging), if critical { "NeedsAttention" } else { "Active" })
+        }
+    };
+    Look { icon, status, text }
+}
+
+/* width, height and ARGB data */
+type Pixmap = (i32, i32, Vec<u8>);
+
+/* org.kde.StatusNotifierItem of the headset */
+struct Item {
+    title: String,
+    look: Look,
+    actions: mpsc::Sender<TrayAction>,
+}
+
+#[interface(name = "org.kde.StatusNotifierItem")]
+impl Item {
+    #[zbus(property)]
+    fn category(&self) -> &str {
+        "Hardware"
+    }
+
+    #[zbus(property)]
+    fn id(&self) -> &str {
+        "airpods-notify"
+    }
+
+    #[zbus(property)]
+    fn title(&self) -> &str {
+        &self.title
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> &str {
+        self.look.status
+    }
+
+    #[zbus(property)]
+    fn icon_name(&self) -> &str {
+        &self.look.icon
+    }
+
+    /* icon name, pixmaps, title and text */
+    #[zbus(property)]
+    fn tool_tip(&self) -> (String, Vec<Pixmap>, String, String) {
+        (self.look.icon.clone(), Vec::new(), self.title.clone(), self.look.text.clone())
+    }
+
+    #[zbus(property)]
+    fn item_is_menu(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn menu(&self) -> ObjectPath<'_> {
+        ObjectPath::from_static_str_unchecked(MENU_PATH)
+    }
+
+    /* a click on the icon shows the details */
+    async fn activate(&self, _x: i32, _y: i32) {
+        let _ = self.actions.send(TrayAction::Details).await;
+    }
+
+    fn secondary_activate(&self, _x: i32, _y: i32) {}
+
+    fn scroll(&self, _delta: i32, _orientation: &str) {}
+
+    #[zbus(signal)]
+    async fn new_icon(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn new_tool_tip(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn new_status(emitter: &SignalEmitter<'_>, status: &str) -> zbus::Result<()>;
+}
+
+/* entry of com.canonical.dbusmenu: id, properties and children in variants */
+#[derive(Serialize, Type)]
+struct Layout(i32, HashMap<&'static str, Value<'static>>, Vec<Value<'static>>);
+
+/* com.canonical.dbusmenu of the item, a flat list of entries */
+struct Menu {
+    revision: u32,
+    connected: bool,
+    muted: bool,
+    actions: mpsc::Sender<TrayAction>,
+}
+
+impl Menu {
+    fn properties(&self, id: i32) -> Option<HashMap<&'static str, Value<'static>>> {
+        let properties = match id {
+            0 => vec![("children-display", Value::from("submenu"))],
+            MENU_CONNECT => vec![("label", Value::from(if self.connected { "Disconnect" } else { "Connect" }))],
+            MENU_MONITOR => vec![("label", Value::from("Open monitor"))],
+            MENU_MUTE => vec![
+                ("label", Value::from("Mute for today")),
+                ("toggle-type", Value::from("checkmark")),
+                ("toggle-state", Value::from(i32::from(self.muted))),
+            ],
+            _ => return None,
+        };
+        Some(properties.into_iter().collect())
+    }
+
+    async fn clicked(&self, id: i32) -> bool {
+        let action = match id {
+            MENU_CONNECT if self.connected => TrayAction::Disconnect,
+            MENU_CONNECT => TrayAction::Connect,
+            MENU_MONITOR => TrayAction::Monitor,
+            MENU_MUTE if self.muted => TrayAction::Unmute,
+            MENU_MUTE => TrayAction::Mute,
+            _ => return false,
+        };
+        let _ = self.actions.send(action).await;
+        true
+    }
+}
+
+#[interface(name = "com.canonical.dbusmenu")]
+impl Menu {
+    fn get_layout(&self, parent: i32, _depth: i32, _names: Vec<String>) -> fdo::Result<(u32, Layout)> {
+        let entry = |id| self.properties(id).ok_or_else(|| fdo::Error::InvalidArgs(format!("no menu entry {}", id)));
+        let children = match parent {
+            0 => [MENU_CONNECT, MENU_MONITOR, MENU_MUTE].into_iter()
+                .map(|id| Ok(Value::from((id, entry(id)?, Vec::<Value>::new()))))
+                .collect::<fdo::Result<_>>()?,
+            _ => Vec::new(),
+        };
+        Ok((self.revision, Layout(parent, entry(parent)?, children)))
+    }
+
+    fn get_group_properties(&self, ids: Vec<i32>, _names: Vec<String>) -> Vec<(i32, HashMap<&'static str, Value<'static>>)> {
+        ids.into_iter()
+            .filter_map(|id| Some((id, self.properties(id)?)))
+            .collect()
+    }
+
+    fn get_property(&self, id: i32, name: &str) -> fdo::Result<OwnedValue> {
+        self.properties(id)
+            .and_then(|mut properties| properties.remove(name))
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("no property {} of menu entry {}", name, id)))
+            .and_then(|value| OwnedValue::try_from(value).map_err(|err| fdo::Error::Failed(err.to_string())))
+    }
+
+    async fn event(&self, id: i32, event: &str, _data: OwnedValue, _timestamp: u32) {
+        if event == "clicked" {
+            self.clicked(id).await;
+        }
+    }
+
+    /* returns the ids which were not found */
+    async fn event_group(&self, events: Vec<(i32, String, OwnedValue, u32)>) -> Vec<i32> {
+        let mut missing = Vec::new();
+        for (id, event, _, _) in events {
+            if event == "clicked" && !self.clicked(id).await {
+                missing.push(id);
+            }
+        }
+        missing
+    }
+
+    fn about_to_show(&self, _id: i32) -> bool {
+        false
+    }
+
+    fn about_to_show_group(&self, _ids: Vec<i32>) -> (Vec<i32>, Vec<i32>) {
+        (Vec::new(), Vec::new())
+    }
+
+    #[zbus(property)]
+    fn version(&self) -> u32 {
+        3
+    }
+
+    #[zbus(property)]
+    fn text_direction(&self) -> &str {
+        "ltr"
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> &str {
+        "normal"
+    }
+
+    #[zbus(property)]
+    fn icon_theme_path(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(signal)]
+    async fn layout_updated(emitter: &SignalEmitter<'_>, revision: u32, parent: i32) -> zbus::Result<()>;
+}
+
+/* Tray icon of the headset through the StatusNotifierItem protocol of KDE,
+ * which is also shown by waybar and GNOME extensions. The icon shows the
+ * lowest bud, the tooltip each part and the menu connects, mutes or opens
+ * the monitor. The chosen entries are sent through the channel. */
+pub struct Tray {
+    connection: Connection,
+    /* name of the registered device */
+    name: String,
+    levels: Levels,
+    card: Option<Card<()>>,
+    connected: bool,
+    muted: bool,
+    registration: JoinHandle<()>,
+}
+
+impl Tray {
+    pub async fn new(
+        connection: Connection,
+        device: &DeviceConfig,
+        levels: Levels,
+        actions: mpsc::Sender<TrayAction>,
+    ) -> zbus::Result<Tray> {
+        let item = Item {
+            title: device.name.clone(),
+            look: look::<()>(None, levels),
+            actions: actions.clone(),
+        };
+        let menu = Menu {
+            revision: 1,
+            connected: false,
+            muted: false,
+            actions,
+        };
+        connection.object_server().at(ITEM_PATH, item).await?;
+        connection.object_server().at(MENU_PATH, menu).await?;
+        let name = format!("org.kde.StatusNotifierItem-{}-1", process::id());
+        connection.request_name(name.as_str()).await?;
+        let watcher = StatusNotifierWatcherProxy::new(&connection).await?;
+        Ok(Tray {
+            connection,
+            name: device.name.clone(),
+            levels,
+            card: None,
+            connected: false,
+            muted: false,
+            registration: tokio::spawn(register(watcher, name)),
+        })
+    }
+
+    pub async fn session(device: &DeviceConfig, levels: Levels, actions: mpsc::Sender<TrayAction>) -> zbus::Result<Tray> {
+        Tray::new(Connection::session().await?, device, levels, actions).await
+    }
+
+    /* the headset as last reported */
+    pub fn card(&self) -> Option<&Card<()>> {
+        self.card.as_ref()
+    }
+
+    /* report of the headset from the advertisements */
+    pub async fn update(&mut self, identity: Option<&Identity>, address: BDAddr, rssi: i16, msg: PairedMessage) -> zbus::Result<()> {
+        match &mut self.card {
+            Some(card) => card.update(identity.cloned(), address, rssi, msg),
+            None => self.card = Some(Card::new(Some(self.name.clone()), identity.cloned(), address, rssi, msg, ())),
+        }
+        if let Some(identity) = identity {
+            self.connected = identity.connected;
+        }
+        self.refresh().await
+    }
+
+    pub async fn set_connected(&mut self, connected: bool) -> zbus::Result<()> {
+        self.connected = connected;
+        self.refresh().await
+    }
+
+    pub async fn set_muted(&mut self, muted: bool) -> zbus::Result<()> {
+        self.muted = muted;
+        self.refresh().await
+    }
+
+    /* signals only what changed, the advertisements arrive every second */
+    async fn refresh(&self) -> zbus::Result<()> {
+        let server = self.connection.object_server();
+        let item = server.interface::<_, Item>(ITEM_PATH).await?;
+        let look = look(self.card.as_ref(), self.levels);
+        let mut item_mut = item.get_mut().await;
+        if item_mut.look != look {
+            let old = std::mem::replace(&mut item_mut.look, look);
+            if old.icon != item_mut.look.icon {
+                Item::new_icon(item.signal_emitter()).await?;
+            }
+            if old.status != item_mut.look.status {
+                Item::new_status(item.signal_emitter(), item_mut.look.status).await?;
+            }
+            Item::new_tool_tip(item.signal_emitter()).await?;
+        }
+
+        let menu = server.interface::<_, Menu>(MENU_PATH).await?;
+        let mut menu_mut = menu.get_mut().await;
+        if (menu_mut.connected, menu_mut.muted) != (self.connected, self.muted) {
+            menu_mut.connected = self.connected;
+            menu_mut.muted = self.muted;
+            menu_mut.revision += 1;
+            Menu::layout_updated(menu.signal_emitter(), menu_mut.revision, 0).await?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Tray {
+    fn drop(&mut self) {
+        self.registration.abort();
+    }
+}
+
+/* registers the item again whenever the watcher starts, e.g. with the panel */
+async fn register(watcher: StatusNotifierWatcherProxy<'static>, name: String) {
+    if let Err(err) = watcher.register_status_notifier_item(&name).await {
+        eprintln!("tray is not available: {}", err);
+    }
+    let mut owners = match watcher.inner().receive_owner_changed().await {
+        Ok(owners) => owners,
+        Err(err) => {
+            eprintln!("tray watcher: {}", err);
+            return;
+        }
+    };
+    while let Some(owner) = owners.next().await {
+        if owner.is_some() {
+            if let Err(err) = watcher.register_status_notifier_item(&name).await {
+                eprintln!("tray is not available: {}", err);
+            }
+        }
+    }
+}
+
+/* runs the monitor of this executable in a terminal window, with the same configuration */
+pub fn open_monitor(terminal: &[String]) {
+    let (command, args) = match terminal.split_first() {
+        Some(terminal) => terminal,
+        None => return,
+    };
+    let executable = match env::current_exe() {
+        Ok(executable) => executable,
+        Err(err) => {
+            eprintln!("monitor window: {}", err);
+            return;
+        }
+    };
+    let config = env::args().skip_while(|arg| arg != "--config").take(2);
+    let result = tokio::process::Command::new(command)
+        .args(args)
+        .arg(executable)
+        .arg("--monitor")
+        .args(config)
+        .spawn();
+    if let Err(err) = result {
+        eprintln!("monitor window: {}: {}", command, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{paired_message, Calls, PrivateBus};
+    use zbus::proxy::CacheProperties;
+
+    fn icon(left: u8, right: u8, charging: bool) -> (String, &'static str) {
+        let levels = Levels::new(&TrayConfig::default(), &NearbyConfig::default());
+        let msg = PairedMessage { left_charging: charging, ..paired_message(left, right, 10) };
+        let card = Card::new(Some("Work".to_string()), None, BDAddr::default(), -50, msg, ());
+        let look = look(Some(&card), levels);
+        (look.icon, look.status)
+    }
+
+    #[test]
+    fn icon_follows_the_lowest_bud() {
+        assert_eq!(icon(10, 9, false), ("battery-full".to_string(), "Active"));
+        assert_eq!(icon(10, 5, false), ("battery-good".to_string(), "Active"));
+        assert_eq!(icon(2, 8, false), ("battery-low".to_string(), "Active"));
+        assert_eq!(icon(1, 8, false), ("battery-caution".to_string(), "NeedsAttention"));
+        assert_eq!(icon(1, 8, true), ("battery-caution-charging".to_string(), "Active"));
+    }
+
+    /* org.kde.StatusNotifierWatcher of the panel, which records the items */
+    struct FakeWatcher {
+        calls: Calls,
+    }
+
+    #[interface(name = "org.kde.StatusNotifierWatcher")]
+    impl FakeWatcher {
+        fn register_status_notifier_item(&self, service: &str) {
+            self.calls.push(format!("Register {}", service));
+        }
+    }
+
+    async fn proxy(connection: &Connection, path: &'static str, interface: &'static str) -> zbus::Proxy<'static> {
+        zbus::proxy::Builder::new(connection)
+            .destination(format!("org.kde.StatusNotifierItem-{}-1", process::id())).unwrap()
+            .path(path).unwrap()
+            .interface(interface).unwrap()
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await
+            .unwrap()
+    }
+
+    /* id, properties and children of a menu entry as read by the panel */
+    type Entry = (i32, HashMap<String, OwnedValue>, Vec<OwnedValue>);
+
+    /* labels of the entries below the root */
+    async fn labels(menu: &zbus::Proxy<'_>) -> Vec<String> {
+        let (_, (_, _, children)): (u32, Entry) = menu.call("GetLayout", &(0, -1, Vec::<String>::new())).await.unwrap();
+        children.into_iter()
+            .map(|child| {
+                let (_, mut properties, _) = Entry::try_from(child).unwrap();
+                String::try_from(properties.remove("label").unwrap()).unwrap()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn item_and_menu_are_served_on_the_bus() {
+        let bus = PrivateBus::start();
+        let watcher = bus.serve("org.kde.StatusNotifierWatcher").await;
+        let calls = Calls::default();
+        watcher.object_server().at("/StatusNotifierWatcher", FakeWatcher { calls: calls.clone() }).await.unwrap();
+
+        let device: DeviceConfig = toml::from_str::<crate::config::Config>("[[device]]\nname = \"Work\"\nmodel = \"AirPodsPro\"\n")
+            .unwrap().device.remove(0);
+        let (actions, mut chosen) = mpsc::channel(8);
+        let levels = Levels::new(&TrayConfig::default(), &NearbyConfig::default());
+        let mut tray = Tray::new(bus.connect().await, &device, levels, actions).await.unwrap();
+        assert_eq!(calls.wait(1).await, [format!("Register org.kde.StatusNotifierItem-{}-1", process::id())]);
+
+        let client = bus.connect().await;
+        let item = proxy(&client, ITEM_PATH, "org.kde.StatusNotifierItem").await;
+        assert_eq!(item.get_property::<String>("Title").await.unwrap(), "Work");
+        assert_eq!(item.get_property::<String>("IconName").await.unwrap(), "audio-headphones");
+        assert_eq!(item.get_property::<String>("Status").await.unwrap(), "Passive");
+
+        tray.update(None, BDAddr::default(), -50, paired_message(2, 8, 10)).await.unwrap();
+        assert_eq!(item.get_property::<String>("IconName").await.unwrap(), "battery-low");
+        assert_eq!(item.get_property::<String>("Status").await.unwrap(), "Active");
+        let (_, _, title, text) = item.get_property::<(String, Vec<Pixmap>, String, String)>("ToolTip").await.unwrap();
+        assert_eq!(title, "Work");
+        assert!(text.starts_with("Left: 20%"), "{}", text);
+
+        let menu = proxy(&client, MENU_PATH, "com.canonical.dbusmenu").await;
+        assert_eq!(labels(&menu).await, ["Connect", "Open monitor", "Mute for today"]);
+        tray.set_connected(true).await.unwrap();
+        assert_eq!(labels(&menu).await, ["Disconnect", "Open monitor", "Mute for today"]);
+
+        let () = menu.call("Event", &(MENU_MUTE, "clicked", Value::from(0), 0u32)).await.unwrap();
+        assert_eq!(chosen.recv().await, Some(TrayAction::Mute));
+        let () = menu.call("Event", &(MENU_CONNECT, "clicked", Value::from(0), 0u32)).await.unwrap();
+        assert_eq!(chosen.recv().await, Some(TrayAction::Disconnect));
+    }
+}